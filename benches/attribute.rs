@@ -0,0 +1,49 @@
+//! Benchmark nested attributes traversal, run with `cargo bench --bench attribute`.
+
+use std::sync::Arc;
+
+use tap::tree::Tree;
+use tap::node::Node;
+use tap::attribute::Attributes;
+use tap::value::Value;
+
+use tap_query::attribute::{MatchMethod, QueryType, QueryOptions, match_query, match_attribute_query};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Create a tree of `count` nodes each having a wide nested attribute with `width` fields of `width` fields.
+fn nested_tree(count : usize, width : usize) -> Tree
+{
+  let tree = Tree::new();
+  for index in 0..count
+  {
+    let node = Node::new(format!("node{}", index));
+    let header = Attributes::new();
+    for field in 0..width
+    {
+      let sub = Attributes::new();
+      for sub_field in 0..width
+      {
+        sub.add_attribute(format!("field{}", sub_field), sub_field as u64, None);
+      }
+      header.add_attribute(format!("struct{}", field), Value::Attributes(Arc::new(sub)), None);
+    }
+    node.value().add_attribute("header", Value::Attributes(Arc::new(header)), None);
+    tree.add_child(tree.root_id, node).unwrap();
+  }
+  tree
+}
+
+fn nested_attributes(c : &mut Criterion)
+{
+  let tree = nested_tree(10_000, 16);
+  let nodes = tree.children_rec(None).unwrap();
+  let options = QueryOptions::default();
+
+  c.bench_function("attribute name nested", |b| b.iter(||
+    match_query(&tree, &nodes, QueryType::AttributeName, MatchMethod::Fixed, "header.struct15.field15", &options).unwrap()));
+  c.bench_function("attribute name and value nested", |b| b.iter(||
+    match_attribute_query(&tree, &nodes, "header.struct15.field15", MatchMethod::Fixed, "15", MatchMethod::Fixed, &options).unwrap()));
+}
+
+criterion_group!(benches, nested_attributes);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+//! Benchmark data searches, run with `cargo bench --bench data`.
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use tap::tree::Tree;
+use tap::node::Node;
+use tap::value::Value;
+use tap::vfile::{VFile, VFileBuilder};
+
+use tap_query::data::{DataMethod, DataSearchOptions, query_data_line, query_data_offsets};
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::{Serialize, Deserialize};
+
+/// VFile builder returning a copy of `data`, so files can be searched without touching the disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct MemoryVFileBuilder
+{
+  data : Vec<u8>,
+}
+
+#[typetag::serde]
+impl VFileBuilder for MemoryVFileBuilder
+{
+  fn open(&self) -> anyhow::Result<Box<dyn VFile>>
+  {
+    Ok(Box::new(Cursor::new(self.data.clone())))
+  }
+
+  fn size(&self) -> u64
+  {
+    self.data.len() as u64
+  }
+}
+
+/// Create a tree of `count` nodes each having a small text file as `data` attribute.
+fn data_tree(count : usize) -> Tree
+{
+  let tree = Tree::new();
+  for index in 0..count
+  {
+    let node = Node::new(format!("file{}", index));
+    let data = format!("line of file {}\n", index).repeat(64).into_bytes();
+    node.value().add_attribute("data", Value::VFileBuilder(Arc::new(MemoryVFileBuilder{ data })), None);
+    tree.add_child(tree.root_id, node).unwrap();
+  }
+  tree
+}
+
+//a searcher is created for each rayon worker rather than for each node, it's the main cost on many small files
+fn line_search(c : &mut Criterion)
+{
+  let tree = data_tree(10_000);
+  let nodes = tree.children_rec(None).unwrap();
+  let options = DataSearchOptions::default();
+
+  c.bench_function("query_data_line 10k nodes", |b| b.iter(|| query_data_line(&tree, &nodes, "file 9999$").unwrap()));
+  c.bench_function("query_data_offsets text 10k nodes", |b| b.iter(|| query_data_offsets(&tree, &nodes, "file 9999", DataMethod::Text, &options).unwrap()));
+}
+
+criterion_group!(benches, line_search);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+//! Benchmark the [Op](tap_query::filter::Op) set operations, run with `cargo bench --bench filter`.
+
+use tap::tree::{Tree, TreeNodeId};
+use tap::node::Node;
+
+use tap_query::filter::Op;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Return the ids of a tree with `count` nodes.
+fn node_ids(count : usize) -> Vec<TreeNodeId>
+{
+  let tree = Tree::new();
+  (0..count).map(|index| tree.add_child(tree.root_id, Node::new(format!("node{}", index))).unwrap()).collect()
+}
+
+//two unsorted 100k inputs sharing half of their ids
+fn set_operations(c : &mut Criterion)
+{
+  let ids = node_ids(150_000);
+  let left : Vec<TreeNodeId> = ids[..100_000].iter().rev().copied().collect();
+  let right : Vec<TreeNodeId> = ids[50_000..].to_vec();
+  let inputs = || (left.clone(), right.clone());
+
+  c.bench_function("Op::and 100k", |b| b.iter_batched(inputs, |(left, right)| Op::and(left, right), BatchSize::LargeInput));
+  c.bench_function("Op::or 100k", |b| b.iter_batched(inputs, |(left, right)| Op::or(left, right), BatchSize::LargeInput));
+  c.bench_function("Op::and_not 100k", |b| b.iter_batched(inputs, |(left, right)| Op::and_not(left, right), BatchSize::LargeInput));
+
+  let (mut sorted_left, mut sorted_right) = (left.clone(), right.clone());
+  sorted_left.sort_unstable();
+  sorted_right.sort_unstable();
+  c.bench_function("Op::and_sorted 100k", |b| b.iter(|| Op::and_sorted(&sorted_left, &sorted_right)));
+}
+
+criterion_group!(benches, set_operations);
+criterion_main!(benches);
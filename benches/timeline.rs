@@ -0,0 +1,41 @@
+//! Benchmark timeline creation, run with `cargo bench --bench timeline`.
+
+use tap::tree::Tree;
+use tap::node::Node;
+
+use tap_query::timeline::Timeline;
+use chrono::{TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Create a tree of `count` nodes each having `per_node` shuffled DateTime attributes.
+fn dated_tree(count : usize, per_node : usize) -> Tree
+{
+  let tree = Tree::new();
+  for index in 0..count
+  {
+    let node = Node::new(format!("node{}", index));
+    for attribute in 0..per_node
+    {
+      let seconds = ((index * per_node + attribute) * 7919 % 1_000_000_007) as i64;
+      node.value().add_attribute(format!("time{}", attribute), Utc.timestamp(seconds, 0), None);
+    }
+    tree.add_child(tree.root_id, node).unwrap();
+  }
+  tree
+}
+
+//the final sort dominates on large timelines once the collection is parallel
+fn timeline_sort(c : &mut Criterion)
+{
+  let tree = dated_tree(1_000_000, 5);
+  let nodes = tree.children_rec(None).unwrap();
+  let (min_time, max_time) = (Utc.timestamp(0, 0), Utc.timestamp(i32::MAX as i64, 0));
+
+  let mut group = c.benchmark_group("timeline");
+  group.sample_size(10);
+  group.bench_function("nodes 5M entries", |b| b.iter(|| Timeline::nodes(&tree, &nodes, &min_time, &max_time)));
+  group.finish();
+}
+
+criterion_group!(benches, timeline_sort);
+criterion_main!(benches);
@@ -1,31 +1,113 @@
 //! Method and Struct use to filter [Node] [Attribute].
 
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
 use tap::node::Node;
 use tap::attribute::Attribute;
 use tap::value::{ValueTypeId, Value};
 use tap::tree::{Tree, TreeNodeId};
 
-use regex::Regex;
+#[cfg(feature = "regex")]
+use regex::{Regex, RegexBuilder};
 use wildmatch::WildMatch;
+#[cfg(feature = "fuzzy")]
 use fuzzy_matcher::FuzzyMatcher;
+#[cfg(feature = "fuzzy")]
 use fuzzy_matcher::clangd::ClangdMatcher;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+#[cfg(feature = "regex")]
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+#[cfg(feature = "regex")]
+use grep_searcher::SearcherBuilder;
+#[cfg(feature = "regex")]
+use grep_searcher::sinks::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
 use rayon::prelude::*;
-use anyhow::Result;
+use rayon::ThreadPool;
+use anyhow::{anyhow, Result};
+
+use crate::progress::{poll_progress, ResultLimit, QueryCancelled};
 
 /**
  *  Different matching methods used by [MatcherMethod].
  */ 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MatchMethod
 {
   /// Compare full string
   Fixed,
+  /// Compare full string ignoring case
+  FixedCaseInsensitive,
+  /// Compare full string ignoring case if the query is all lowercase, case sensitive otherwise
+  SmartCase,
+  /// Match if the string contains the query, ignoring case if the query is all lowercase
+  SmartCaseContains,
+  /// Match if the string contains the query
+  Contains,
+  /// Match if the string starts with the query
+  StartsWith,
+  /// Match if the string ends with the query
+  EndsWith,
   /// Compare using regexp
   Regex,
   /// Compare using wildcard
   Wildcard,
+  /// Compare using wildcard ignoring case
+  WildcardCaseInsensitive,
+  /// Compare dotted names using a wildcard for each dot separated segment, `*` match inside a segment and `**` match any number of segments (`**.mtime`)
+  DottedGlob,
   /// Compare using fuzzy matching
   Fuzzy,
+  /// Compare using fuzzy matching, only match if the score is at least the threshold
+  FuzzyWithThreshold(i64),
+}
+
+/// Return true if the dotted name `names` segments match the glob `segments`, a `None` segment (`**`) match zero or more names.
+fn match_segments(segments : &[Option<WildMatch>], names : &[&str]) -> bool
+{
+  match segments.split_first()
+  {
+    None => names.is_empty(),
+    Some((None, segments)) => (0..=names.len()).any(|skip| match_segments(segments, &names[skip..])),
+    Some((Some(segment), segments)) => match names.split_first()
+    {
+      Some((name, names)) => segment.matches(name) && match_segments(segments, names),
+      None => false,
+    },
+  }
+}
+
+/**
+ *  Limits applied when compiling a user supplied regex, so a pathological pattern return an error rather than using a huge amount of memory.
+ *  Regex matching time is linear in the size of the searched value, so only the compiled size need to be bounded.
+ */
+#[derive(Debug, Clone)]
+pub struct RegexLimits
+{
+  /// Maximum size in bytes of the compiled regex.
+  pub size_limit : usize,
+  /// Maximum size in bytes of the cache used by the lazy DFA, the regex is still matched if it's exceeded but slower.
+  pub dfa_size_limit : usize,
+}
+
+impl Default for RegexLimits
+{
+  fn default() -> Self
+  {
+    RegexLimits{ size_limit : 10 * (1 << 20), dfa_size_limit : 2 * (1 << 20) }
+  }
+}
+
+/// Convert a regex compilation error to an error that tell which limit was exceeded.
+#[cfg(feature = "regex")]
+pub(crate) fn regex_error(error : regex::Error) -> anyhow::Error
+{
+  match error
+  {
+    regex::Error::CompiledTooBig(limit) => anyhow!("Regex is too big, its compiled size exceeds the limit of {} bytes", limit),
+    error => error.into(),
+  }
 }
 
 /**
@@ -34,22 +116,85 @@ pub enum MatchMethod
 pub enum MatcherMethod
 {
   Fixed,
+  /// Contain the lowercased query.
+  FixedCaseInsensitive(String),
+  Contains,
+  /// Contain the lowercased query.
+  ContainsCaseInsensitive(String),
+  StartsWith,
+  EndsWith,
+  #[cfg(feature = "regex")]
   Regex(Regex),
   Wildcard(WildMatch),
+  /// Contain the lowercased pattern.
+  WildcardCaseInsensitive(WildMatch),
+  /// Contain a wildcard for each segment of the pattern, `None` for `**`.
+  DottedGlob(Vec<Option<WildMatch>>),
+  /// Match if any of the patterns compiled in the set match, created with [MatcherMethod::glob_set].
+  GlobSet(GlobSet),
+  #[cfg(feature = "fuzzy")]
   Fuzzy(ClangdMatcher),
+  #[cfg(feature = "fuzzy")]
+  FuzzyWithThreshold(ClangdMatcher, i64),
 }
 
 impl MatcherMethod
 {
-  /// Instantiate a new Matcher using `method_type` compiled with `query`.
+  /// Instantiate a new Matcher using `method_type` compiled with `query`, regex are compiled with the default [RegexLimits].
   pub fn new(method_type : &MatchMethod, query : &str) -> Result<MatcherMethod>
+  {
+    MatcherMethod::new_with_limits(method_type, query, &RegexLimits::default())
+  }
+
+  /// Instantiate a Matcher that match if any of the glob `patterns` match, all the patterns are compiled in a single [GlobSet].
+  /// The query passed to [MatcherMethod::is_match] is ignored.
+  pub fn glob_set(patterns : &[&str]) -> Result<MatcherMethod>
+  {
+    Ok(MatcherMethod::GlobSet(build_glob_set(patterns)?))
+  }
+
+  /// Same as [MatcherMethod::new] but compile regex with `limits`, return an error if the compiled regex exceeds them.
+  #[cfg_attr(not(feature = "regex"), allow(unused_variables))]
+  pub fn new_with_limits(method_type : &MatchMethod, query : &str, limits : &RegexLimits) -> Result<MatcherMethod>
   {
     match method_type 
     {
       MatchMethod::Fixed => Ok(MatcherMethod::Fixed),
-      MatchMethod::Regex => Ok(MatcherMethod::Regex(Regex::new(query)?)),
+      MatchMethod::FixedCaseInsensitive => Ok(MatcherMethod::FixedCaseInsensitive(query.to_lowercase())),
+      //the query is a literal, a backslash doesn't start an escape like with has_uppercase_literal
+      MatchMethod::SmartCase => match query.chars().any(char::is_uppercase)
+      {
+        true => Ok(MatcherMethod::Fixed),
+        false => Ok(MatcherMethod::FixedCaseInsensitive(query.to_lowercase())),
+      },
+      MatchMethod::SmartCaseContains => match query.chars().any(char::is_uppercase)
+      {
+        true => Ok(MatcherMethod::Contains),
+        false => Ok(MatcherMethod::ContainsCaseInsensitive(query.to_lowercase())),
+      },
+      MatchMethod::Contains => Ok(MatcherMethod::Contains),
+      MatchMethod::StartsWith => Ok(MatcherMethod::StartsWith),
+      MatchMethod::EndsWith => Ok(MatcherMethod::EndsWith),
+      #[cfg(feature = "regex")]
+      MatchMethod::Regex => Ok(MatcherMethod::Regex(RegexBuilder::new(query).size_limit(limits.size_limit)
+                                                                             .dfa_size_limit(limits.dfa_size_limit)
+                                                                             .build()
+                                                                             .map_err(regex_error)?)),
+      #[cfg(not(feature = "regex"))]
+      MatchMethod::Regex => Err(anyhow!("Regex matching is disabled, build with the regex feature")),
       MatchMethod::Wildcard => Ok(MatcherMethod::Wildcard(WildMatch::new(query))), 
+      MatchMethod::WildcardCaseInsensitive => Ok(MatcherMethod::WildcardCaseInsensitive(WildMatch::new(&query.to_lowercase()))),
+      MatchMethod::DottedGlob => Ok(MatcherMethod::DottedGlob(query.split('.').map(|segment| match segment
+      {
+        "**" => None,
+        segment => Some(WildMatch::new(segment)),
+      }).collect())),
+      #[cfg(feature = "fuzzy")]
       MatchMethod::Fuzzy => Ok(MatcherMethod::Fuzzy(ClangdMatcher::default())),
+      #[cfg(feature = "fuzzy")]
+      MatchMethod::FuzzyWithThreshold(threshold) => Ok(MatcherMethod::FuzzyWithThreshold(ClangdMatcher::default(), *threshold)),
+      #[cfg(not(feature = "fuzzy"))]
+      MatchMethod::Fuzzy | MatchMethod::FuzzyWithThreshold(_) => Err(anyhow!("Fuzzy matching is disabled, build with the fuzzy feature")),
     }
   }
 
@@ -59,135 +204,405 @@ impl MatcherMethod
     match &self
     {
       MatcherMethod::Fixed => value == query,
+      MatcherMethod::FixedCaseInsensitive(query) => value.to_lowercase() == *query,
+      MatcherMethod::Contains => value.contains(query),
+      MatcherMethod::ContainsCaseInsensitive(query) => value.to_lowercase().contains(query.as_str()),
+      MatcherMethod::StartsWith => value.starts_with(query),
+      MatcherMethod::EndsWith => value.ends_with(query),
+      #[cfg(feature = "regex")]
       MatcherMethod::Regex(matcher) => matcher.is_match(value),
       MatcherMethod::Wildcard(matcher) => matcher.matches(value),
-      MatcherMethod::Fuzzy(matcher) => matcher.fuzzy_match(value, query).is_some()
+      MatcherMethod::WildcardCaseInsensitive(matcher) => matcher.matches(&value.to_lowercase()),
+      MatcherMethod::DottedGlob(segments) => match_segments(segments, &value.split('.').collect::<Vec<&str>>()),
+      MatcherMethod::GlobSet(set) => set.is_match(value),
+      #[cfg(feature = "fuzzy")]
+      MatcherMethod::Fuzzy(matcher) => matcher.fuzzy_match(value, query).is_some(),
+      #[cfg(feature = "fuzzy")]
+      MatcherMethod::FuzzyWithThreshold(matcher, threshold) => matcher.fuzzy_match(value, query).map_or(false, |score| score >= *threshold),
+    }
+  }
+
+  /// Return the score of `value` matching `query`, or `None` if it doesn't match.
+  /// Only fuzzy methods compute a real score, other methods return [MATCH_SCORE] on match.
+  pub fn score(&self, query : &str, value : &str) -> Option<i64>
+  {
+    match &self
+    {
+      #[cfg(feature = "fuzzy")]
+      MatcherMethod::Fuzzy(matcher) => matcher.fuzzy_match(value, query),
+      #[cfg(feature = "fuzzy")]
+      MatcherMethod::FuzzyWithThreshold(matcher, threshold) => matcher.fuzzy_match(value, query).filter(|score| score >= threshold),
+      _ => match self.is_match(query, value)
+      {
+        true => Some(MATCH_SCORE),
+        false => None,
+      },
     }
   }
 }
 
+/// Score returned by [MatcherMethod::score] for non fuzzy matches.
+pub const MATCH_SCORE : i64 = i64::MAX;
+
 /**
  *  Select on which nodes variable attribute is matched.
  */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum QueryType
 {
   /// Match on a node attribute name.
   AttributeName,
+  /// Match on a node attribute value converted to string, whatever the attribute name, [opaque](is_opaque_value) values are skipped.
+  AttributeValue,
   /// Match on node name.
   Name,
+  /// Match on node full path in the tree.
+  Path,
+}
+
+/**
+ *  Select how the name and the value of an [Attribute] are combined for the attribute to match.
+ */
+#[derive(Debug, Clone)]
+pub enum AttributeCombine
+{
+  /// Both the name and the value must match.
+  And,
+  /// The name or the value must match.
+  Or,
+}
+
+/**
+ *  Select how attribute names of a [Node] must match for the node to be returned by [match_attribute_name_mode].
+ */
+#[derive(Debug)]
+pub enum AttributeMatchMode
+{
+  /// Node match if any of its attribute names match the query.
+  Any,
+  /// Node match only if the query and each of these patterns match at least one of its attribute names.
+  AllPresent(Vec<String>),
+}
+
+/**
+ *  Options of [match_query] and [match_attribute_query], [QueryOptions::default] match every node without any restriction.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions
+{
+  /// Nodes ignored without being fetched from the tree (only used by [match_query]).
+  pub skip : HashSet<TreeNodeId>,
+  /// If set, only the ReflectStruct fields with one of these names are matched and descended into, 
+  /// so attributes found in other fields are not considered.
+  pub reflect_fields : Option<Vec<String>>,
+  /// If set, nested attributes are not descended into deeper than `max_depth` levels (0 only match first-level attributes).
+  pub max_depth : Option<usize>,
+  /// If set, nodes stop being matched once `limit` matches were found and at most `limit` ids are returned, 
+  /// they're not necessarily the first matching ones as nodes are matched in parallel.
+  pub limit : Option<usize>,
+  /// If set and the value is matched with [MatchMethod::Regex], stringified values longer than `line_threshold` bytes 
+  /// are searched line by line with the grep searcher (only used by [match_attribute_query]).
+  pub line_threshold : Option<usize>,
+  /// Convert opaque values (see [is_opaque_value]) to string and match them, 
+  /// otherwise attributes with an opaque value never match (only used by [match_attribute_query]).
+  pub opaque_values : bool,
+  /// Limits applied when compiling regex, including the one used to match values line by line.
+  pub regex_limits : RegexLimits,
 }
 
 /**
  *  Multithreaded function that iterate on `nodes` and return if `query_value` matched [Node] [Attribute] using `match_method_type` [MatcherMethod].
+ *  `options` can skip nodes, restrict the matched ReflectStruct fields and nested depth, and limit the number of returned ids (see [QueryOptions]).
+ *  Returned ids are sorted and unique, even if `nodes` contains duplicates.
  */
-pub fn match_query(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str) -> Result<Vec<TreeNodeId>>
+pub fn match_query(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, options : &QueryOptions) -> Result<Vec<TreeNodeId>>
 {
   //We reuse the same matcher in every thread (there should be all multithreadable)
+  let matcher = MatcherMethod::new_with_limits(&match_method_type, query_value, &options.regex_limits)?;
+  Ok(match_query_nodes(tree, nodes, &query_type, &matcher, query_value, &options.skip, options.reflect_fields.as_deref(), options.max_depth, options.limit, None, None))
+}
+
+/**
+ *  Same as [match_query] but use `matcher` rather than compiling a new one, so the same [MatcherMethod] can be reused between queries.
+ *  `matcher` must have been created with `query_value`.
+ */
+pub fn match_query_with_matcher(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, matcher : &MatcherMethod, query_value : &str) -> Vec<TreeNodeId>
+{
+  match_query_nodes(tree, nodes, &query_type, matcher, query_value, &HashSet::new(), None, None, None, None, None)
+}
+
+/**
+ *  Same as [match_query] but match `query_type` with a list of glob `patterns` compiled once in a [GlobSet], a node match if any pattern match.
+ *  It's faster than combining a query for each pattern when there is many patterns (list of extensions or of paths).
+ */
+pub fn match_query_globset(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, patterns : &[&str]) -> Result<Vec<TreeNodeId>>
+{
+  let matcher = MatcherMethod::glob_set(patterns)?;
+  Ok(match_query_with_matcher(tree, nodes, query_type, &matcher, ""))
+}
+
+/**
+ *  Same as [match_query] but run inside `pool` rather than the global rayon thread pool, so the number of threads used by the query can be bounded.
+ */
+pub fn match_query_in_pool(pool : &ThreadPool, tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, options : &QueryOptions) -> Result<Vec<TreeNodeId>>
+{
+  pool.install(|| match_query(tree, nodes, query_type, match_method_type, query_value, options))
+}
+
+/**
+ *  Same as [match_query] but call `progress` with the number of nodes already processed.
+ *  The query run on another thread and `progress` is called periodically from the caller thread, so it doesn't need to be [Sync].
+ */
+pub fn match_query_progress<F : FnMut(usize)>(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, progress : F) -> Result<Vec<TreeNodeId>>
+{
   let matcher = MatcherMethod::new(&match_method_type, query_value)?;
+  Ok(poll_progress(|processed| match_query_nodes(tree, nodes, &query_type, &matcher, query_value, &HashSet::new(), None, None, None, None, Some(processed)), progress))
+}
 
-  Ok(nodes.par_iter().filter_map(|node_id|
+/**
+ *  Same as [match_query] but stop early when `cancel` is set to true.
+ *  No partial results are returned : a [QueryCancelled] error is returned if the query was cancelled.
+ */
+pub fn match_query_cancellable(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, cancel : &AtomicBool) -> Result<Vec<TreeNodeId>>
+{
+  let matcher = MatcherMethod::new(&match_method_type, query_value)?;
+  let result = match_query_nodes(tree, nodes, &query_type, &matcher, query_value, &HashSet::new(), None, None, None, Some(cancel), None);
+
+  match cancel.load(Ordering::Relaxed)
   {
-     if let Some(node) = tree.get_node_from_id(*node_id)
-     {
-       let is_match = match query_type //match query type for each node, can do it one time
-       {
-         //Compare node name to query value
-         QueryType::Name => matcher.is_match(query_value, &node.name()),
-         QueryType::AttributeName => match_attributes_dotted_name(&node, query_value, &matcher),
-       }; 
-       if is_match 
-       {
-         return Some(*node_id)
-       }
-     }
-     None
-  }).collect())
+    true => Err(QueryCancelled.into()),
+    false => Ok(result),
+  }
 }
 
-fn match_attributes_dotted_name(node : &Node, query_value : &str, matcher: &MatcherMethod) -> bool
+/**
+ *  Same as [match_query] but only return the number of matching nodes, results are not collected so it's faster on huge [Tree].
+ *  Duplicated ids in `nodes` are counted once and `options` apply the same way, so the count is the length of the [match_query] result.
+ */
+pub fn match_query_count(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, options : &QueryOptions) -> Result<usize>
 {
-  for attribute in node.value().attributes().iter()
+  let matcher = MatcherMethod::new_with_limits(&match_method_type, query_value, &options.regex_limits)?;
+  let unique : HashSet<TreeNodeId> = nodes.iter().filter(|node_id| !options.skip.contains(node_id)).copied().collect();
+
+  let matched = unique.par_iter().filter(|node_id| match_query_node(tree, node_id, &query_type, &matcher, query_value, options.reflect_fields.as_deref(), options.max_depth)).count();
+  Ok(options.limit.map_or(matched, |limit| matched.min(limit)))
+}
+
+/**
+ *  Statistics about the nodes and attributes inspected by a query, returned by [match_query_stats].
+ */
+#[derive(Debug, Clone, Default)]
+pub struct QueryStats
+{
+  /// Number of nodes found in the [Tree] and matched against the query.
+  pub nodes_inspected : usize,
+  /// Number of attributes (including nested ones) of the inspected nodes, 0 for queries that don't match attributes.
+  pub attributes_inspected : u64,
+  /// Deepest attribute level of the inspected nodes (1 for first-level attributes), 0 if no attribute was inspected.
+  pub max_depth_reached : usize,
+}
+
+/**
+ *  Same as [match_query] but also return [QueryStats], to tell apart a query that matched nothing from a query that had nothing to match.
+ */
+pub fn match_query_stats(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str) -> Result<(Vec<TreeNodeId>, QueryStats)>
+{
+  let matcher = MatcherMethod::new(&match_method_type, query_value)?;
+  let with_attributes = matches!(query_type, QueryType::AttributeName | QueryType::AttributeValue);
+
+  let results : Vec<(Option<TreeNodeId>, QueryStats)> = nodes.par_iter().filter_map(|node_id|
   {
-    if match_attribute_dotted_name("".into(), &attribute, &query_value, &matcher) 
+    let node = tree.get_node_from_id(*node_id)?;
+    let mut stats = QueryStats{ nodes_inspected : 1, ..Default::default() };
+    if with_attributes
     {
-      return true
+      for attribute in node.value().attributes().iter()
+      {
+        attribute_stats(&attribute, 1, &mut stats);
+      }
     }
+    let is_match = match_query_node(tree, node_id, &query_type, &matcher, query_value, None, None);
+    Some((is_match.then(|| *node_id), stats))
+  }).collect();
+
+  let mut stats = QueryStats::default();
+  let mut result = Vec::new();
+  for (node_id, node_stats) in results
+  {
+    stats.nodes_inspected += node_stats.nodes_inspected;
+    stats.attributes_inspected += node_stats.attributes_inspected;
+    stats.max_depth_reached = stats.max_depth_reached.max(node_stats.max_depth_reached);
+    result.extend(node_id);
   }
-  return false
+  result.sort_unstable();
+  result.dedup();
+  Ok((result, stats))
 }
 
-fn match_attribute_dotted_name(dotted_attrib: String, attribute : &Attribute, query_value : &str, matcher: &MatcherMethod) -> bool 
+fn attribute_stats(attribute : &Attribute, depth : usize, stats : &mut QueryStats)
 {
+  stats.attributes_inspected += 1;
+  stats.max_depth_reached = stats.max_depth_reached.max(depth);
+
   if attribute.type_id() == ValueTypeId::Attributes
   {
-      for current_attribute in attribute.value().as_attributes().attributes().iter()
-      {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        if match_attribute_dotted_name(dotted_attrib, &current_attribute, &query_value, &matcher)
-        {
-          return true
-        }
-      }
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      attribute_stats(&current_attribute, depth + 1, stats);
+    }
   }
-  else if attribute.type_id() == ValueTypeId::ReflectStruct 
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
   {
-      let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
-      for current_attribute in attributes.iter() 
-      {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        if match_attribute_dotted_name(dotted_attrib, &current_attribute, &query_value, &matcher)
-        {
-          return true
-        }
-      }
+    for current_attribute in attribute.value().as_reflect_struct().attributes().iter()
+    {
+      attribute_stats(&current_attribute, depth + 1, stats);
+    }
   }
-  match dotted_attrib.len() 
+}
+
+/**
+ *  Return if the [Node] `node_id` match `query_value` like [match_query], without iterating in parallel on a list of nodes.
+ *  Return false if the node can't be found in the [Tree].
+ */
+pub fn match_node(tree : &Tree, node_id : TreeNodeId, query_type : QueryType, match_method_type : MatchMethod, query_value : &str) -> Result<bool>
+{
+  let matcher = MatcherMethod::new(&match_method_type, query_value)?;
+  Ok(match_query_node(tree, &node_id, &query_type, &matcher, query_value, None, None))
+}
+
+fn match_query_node(tree : &Tree, node_id : &TreeNodeId, query_type : &QueryType, matcher : &MatcherMethod, query_value : &str, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> bool
+{
+  tree.get_node_from_id(*node_id).map_or(false, |node|
   {
-    0 => matcher.is_match(query_value, &attribute.name()),
-    _ => matcher.is_match(query_value, &(dotted_attrib + "." + attribute.name())),
-  }
+    match query_type //match query type for each node, can do it one time
+    {
+      //Compare node name to query value
+      QueryType::Name => matcher.is_match(query_value, &node.name()),
+      //Compare node path to query value, the path is only built for this query type
+      QueryType::Path => tree.node_path(*node_id).map_or(false, |path| matcher.is_match(query_value, &path)),
+      QueryType::AttributeName => match_attributes_dotted_name(&node, query_value, &matcher, reflect_fields, max_depth),
+      QueryType::AttributeValue => match_attributes_value(&node, query_value, &matcher, reflect_fields, max_depth),
+    }
+  })
 }
 
+fn match_query_nodes(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : &QueryType, matcher : &MatcherMethod, query_value : &str, skip : &HashSet<TreeNodeId>, reflect_fields : Option<&[String]>, max_depth : Option<usize>, limit : Option<usize>, cancel : Option<&AtomicBool>, processed : Option<&AtomicUsize>) -> Vec<TreeNodeId>
+{
+  let limit = ResultLimit::new(limit);
+
+  let mut result = nodes.par_iter().filter_map(|node_id|
+  {
+     if skip.contains(node_id) || limit.is_reached() || cancel.map_or(false, |cancel| cancel.load(Ordering::Relaxed))
+     {
+       return None
+     }
+     let is_match = match_query_node(tree, node_id, query_type, matcher, query_value, reflect_fields, max_depth);
+     if let Some(processed) = processed
+     {
+       processed.fetch_add(1, Ordering::Relaxed);
+     }
+     if is_match
+     {
+       limit.found();
+     }
+     is_match.then(|| *node_id)
+  }).collect::<Vec<_>>();
+
+  //Op combinators expect unique ids
+  result.sort_unstable();
+  result.dedup();
+  limit.truncate(&mut result);
+  result
+}
 
 /**
- *  Match query on a specific attribute `name` on a specific `value` 
- *  both (name and value) having their specific [MatchMethod] 
- *  and attribute `name` use the dotted notation 
- *  attribute:' ' == '' , attribute:w:'' == ''.
- **/
-pub fn match_attribute_query(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod) -> Result<Vec<TreeNodeId>>
+ *  Same as [match_query] but return the matching nodes with their [score](MatcherMethod::score), sorted by descending score.
+ *  For [QueryType::AttributeName] the score of a node is the best score of its dotted attribute names,
+ *  for [QueryType::AttributeValue] the best score of its attribute values.
+ */
+pub fn match_query_ranked(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str) -> Result<Vec<(TreeNodeId, i64)>>
 {
-  //We reuse the same matcher in every thread (there should be all multithreadable)
-  let name_matcher = MatcherMethod::new(&name_match_type, name)?;
-  let value_matcher = MatcherMethod::new(&value_match_type, value)?;
+  let matcher = MatcherMethod::new(&match_method_type, query_value)?;
 
-  Ok(nodes.par_iter().filter_map(|node_id|
+  let mut result : Vec<(TreeNodeId, i64)> = nodes.par_iter().filter_map(|node_id|
   {
-    if let Some(node) = tree.get_node_from_id(*node_id)
+    let node = tree.get_node_from_id(*node_id)?;
+    let score = match query_type
+    {
+      QueryType::Name => matcher.score(query_value, &node.name()),
+      QueryType::Path => tree.node_path(*node_id).and_then(|path| matcher.score(query_value, &path)),
+      QueryType::AttributeName => attributes_dotted_names(&node, None, None).iter().filter_map(|name| matcher.score(query_value, name)).max(),
+      QueryType::AttributeValue => attributes_dotted_values(&node, None, None).iter().filter_map(|(_, value)| matcher.score(query_value, value)).max(),
+    }?;
+    Some((*node_id, score))
+  }).collect();
+
+  result.par_sort_by(|a, b| b.1.cmp(&a.1));
+  Ok(result)
+}
+
+/**
+ *  Multithreaded function that iterate on `nodes` and return the nodes which full path in the [Tree] match the regex `pattern`.
+ *  The regex is compiled once with the default [RegexLimits] and an error is returned before any node is visited if it's invalid.
+ */
+#[cfg(feature = "regex")]
+pub fn match_path_regex(tree : &Tree, nodes : &Vec<TreeNodeId>, pattern : &str) -> Result<Vec<TreeNodeId>>
+{
+  let limits = RegexLimits::default();
+  let regex = RegexBuilder::new(pattern).size_limit(limits.size_limit)
+                                        .dfa_size_limit(limits.dfa_size_limit)
+                                        .build()
+                                        .map_err(regex_error)?;
+
+  //each worker cache the path of the parents it already resolved, so a path is built from its parent path and name 
+  //rather than by walking up to the root for every node, siblings are contiguous in `nodes` returned by children_rec
+  Ok(nodes.par_iter().map_init(HashMap::new, |parent_paths : &mut HashMap<TreeNodeId, Option<String>>, node_id|
+  {
+    let path = match tree.parent_id(*node_id)
     {
-      if match_attribute_name_value(&node, &name, &name_matcher, &value, &value_matcher) 
+      Some(parent_id) => 
       {
-        return Some(*node_id)
-      }
-    }
-    None
+        let parent_path = parent_paths.entry(parent_id).or_insert_with(|| tree.node_path(parent_id)).as_ref()?;
+        format!("{}/{}", parent_path, tree.get_node_from_id(*node_id)?.name())
+      },
+      None => tree.node_path(*node_id)?,
+    };
+    regex.is_match(&path).then(|| *node_id)
+  }).flatten().collect())
+}
+
+/**
+ *  Multithreaded function that match the name of each of `nodes` against all the wildcard `patterns` at once 
+ *  and return the matching nodes with the index of the first pattern that matched.
+ *  The patterns are compiled in a single [GlobSet] so the cost doesn't grow with each pattern.
+ */
+pub fn match_names_patterns(tree : &Tree, nodes : &Vec<TreeNodeId>, patterns : &[&str]) -> Result<Vec<(TreeNodeId, usize)>>
+{
+  let glob_set = build_glob_set(patterns)?;
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    let index = glob_set.matches(node.name()).into_iter().min()?;
+    Some((*node_id, index))
   }).collect())
 }
 
-fn match_attribute_name_value(node : &Node, query_attr_name : &str, name_matcher : &MatcherMethod, query_attr_value : &str, value_matcher : &MatcherMethod) -> bool
+fn build_glob_set(patterns : &[&str]) -> Result<GlobSet>
+{
+  let mut builder = GlobSetBuilder::new();
+  for pattern in patterns
+  {
+    builder.add(Glob::new(pattern)?);
+  }
+  Ok(builder.build()?)
+}
+
+fn match_attributes_dotted_name(node : &Node, query_value : &str, matcher: &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> bool
 {
   for attribute in node.value().attributes().iter()
   {
-    if match_attribute_name_and_value("".into(), &attribute, &query_attr_name, &name_matcher, &query_attr_value, &value_matcher) 
+    if match_attribute_dotted_name("", &attribute, &query_value, &matcher, reflect_fields, max_depth) 
     {
       return true
     }
@@ -195,111 +610,1139 @@ fn match_attribute_name_value(node : &Node, query_attr_name : &str, name_matcher
   return false
 }
 
-
-fn match_attribute_name_and_value(dotted_attrib: String, attribute: &Attribute, query_attr_name : &str, name_matcher : &MatcherMethod, query_attr_value : &str, value_matcher : &MatcherMethod) -> bool
+//max_depth is the number of levels that can still be descended into
+fn match_attribute_dotted_name(dotted_attrib: &str, attribute : &Attribute, query_value : &str, matcher: &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> bool 
 {
-  if attribute.type_id() == ValueTypeId::Attributes
+  if max_depth == Some(0)
+  {
+    //don't descend, only match this attribute
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
   {
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
       for current_attribute in attribute.value().as_attributes().attributes().iter()
       {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        if match_attribute_name_and_value(dotted_attrib, current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher)
+        if match_attribute_dotted_name(&dotted_attrib, &current_attribute, &query_value, &matcher, reflect_fields, max_depth.map(|depth| depth - 1))
         {
-          return true;
+          return true
         }
       }
   }
   else if attribute.type_id() == ValueTypeId::ReflectStruct 
-  { 
-      //we transform it to attributes 
-      let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes(); //XXX inefficent we copy lot of data for nothing
+  {
+      let attributes : Vec<Attribute> = reflect_struct_attributes(attribute, reflect_fields);
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
       for current_attribute in attributes.iter() 
       {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        if match_attribute_name_and_value(dotted_attrib, current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher)
+        if match_attribute_dotted_name(&dotted_attrib, &current_attribute, &query_value, &matcher, reflect_fields, max_depth.map(|depth| depth - 1))
         {
-          return true;
+          return true
         }
       }
-
   }
   match dotted_attrib.len() 
   {
-    0 => name_matcher.is_match(query_attr_name, &attribute.name()) && 
-         value_matcher.is_match(&query_attr_value, &attribute.value().to_string()),
-    _ => name_matcher.is_match(query_attr_name, &(dotted_attrib + "." + attribute.name())) && 
-         value_matcher.is_match(&query_attr_value, &attribute.value().to_string()),
+    0 => matcher.is_match(query_value, &attribute.name()),
+    _ => matcher.is_match(query_value, &(dotted_attrib.to_string() + "." + attribute.name())),
   }
 }
 
-/// Count attributes recursively.
-fn attributes_count_rec(value: &Value) -> u64
+
+fn match_attributes_value(node : &Node, query_value : &str, matcher : &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> bool
 {
-  let mut counter = 0;
-  if value.type_id() == ValueTypeId::Attributes
+  node.value().attributes().iter().any(|attribute| match_attribute_value(attribute, query_value, matcher, reflect_fields, max_depth))
+}
+
+//only leaf attributes values are matched, a container value converted to string is not meaningful
+//max_depth is the number of levels that can still be descended into
+fn match_attribute_value(attribute : &Attribute, query_value : &str, matcher : &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> bool
+{
+  if attribute.type_id() == ValueTypeId::Attributes
   {
-    for current_attribute in value.as_attributes().attributes().iter()
-    {
-      counter += attributes_count_rec(&current_attribute.value());
-    }
+    max_depth != Some(0) && attribute.value().as_attributes().attributes().iter().any(|current_attribute|
+      match_attribute_value(current_attribute, query_value, matcher, reflect_fields, max_depth.map(|depth| depth - 1)))
   }
-  else if value.type_id() == ValueTypeId::ReflectStruct 
-  { 
-    for attribute in value.as_reflect_struct().attributes().iter()
-    {
-      counter += attributes_count_rec(&attribute.value());
-    }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    max_depth != Some(0) && reflect_struct_attributes(attribute, reflect_fields).iter().any(|current_attribute|
+      match_attribute_value(current_attribute, query_value, matcher, reflect_fields, max_depth.map(|depth| depth - 1)))
   }
   else
   {
-    counter = 1;
+    !is_opaque_value(attribute) && matcher.is_match(query_value, &attribute.value().to_string())
   }
-  return counter;
 }
 
-/// Count attributes for all [Node] in the [Tree].
-pub fn attribute_count(tree : &Tree) -> u64
+/// Return the dotted name and the value converted to string of all the leaf [Attribute] of `node`.
+fn attributes_dotted_values(node : &Node, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> Vec<(String, String)>
 {
-  let nodes = tree.children_rec(None).unwrap();
-  nodes.par_iter().map(|node_id|
+  let mut values = Vec::new();
+  for attribute in node.value().attributes().iter()
   {
-    if let Some(node) = tree.get_node_from_id(*node_id)
-    {
-      let mut counter = 0;
-      for attribute in node.value().attributes().iter()
-      {
-        counter += attributes_count_rec(&attribute.value());
-      }
-      return counter;
-    }
-    0 
-
-  }).sum()
+    attribute_dotted_values("", &attribute, reflect_fields, max_depth, &mut values);
+  }
+  values
 }
 
-/**
- * Multithread function that search all [Node] in the tree and return the one that have a first-level [Attribute] of type [ValueTypeId::VFileBuilder].
- */
-//XXX we should search recursively if attribute contain an other vfiles
-pub fn find_vfiles(tree : &Tree) -> Vec<TreeNodeId>
+//max_depth is the number of levels that can still be descended into, containers have no value of their own
+fn attribute_dotted_values(dotted_attrib : &str, attribute : &Attribute, reflect_fields : Option<&[String]>, max_depth : Option<usize>, values : &mut Vec<(String, String)>)
+{
+  if attribute.type_id() == ValueTypeId::Attributes
+  {
+    if max_depth == Some(0)
+    {
+      return
+    }
+    let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      attribute_dotted_values(&dotted_attrib, &current_attribute, reflect_fields, max_depth.map(|depth| depth - 1), values);
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    if max_depth == Some(0)
+    {
+      return
+    }
+    let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+    for current_attribute in reflect_struct_attributes(attribute, reflect_fields).iter()
+    {
+      attribute_dotted_values(&dotted_attrib, &current_attribute, reflect_fields, max_depth.map(|depth| depth - 1), values);
+    }
+  }
+  else if !is_opaque_value(attribute)
+  {
+    values.push((child_dotted_name(dotted_attrib, attribute), attribute.value().to_string()));
+  }
+}
+
+/**
+ *  Return true if `attribute` value is opaque binary content (like a [VFileBuilder](ValueTypeId::VFileBuilder)),
+ *  converting it to string doesn't give its content and can be costly, so it's skipped when matching values unless asked otherwise.
+ */
+pub fn is_opaque_value(attribute : &Attribute) -> bool
+{
+  attribute.type_id() == ValueTypeId::VFileBuilder
+}
+
+/// Return the dotted name of `attribute` which is also the prefix of its children, it's computed once by container rather than once by child.
+pub(crate) fn child_dotted_name(dotted_attrib : &str, attribute : &Attribute) -> String
+{
+  match dotted_attrib.len()
+  {
+    0 => attribute.name().to_string(),
+    _ => dotted_attrib.to_string() + "." + attribute.name(),
+  }
+}
+
+/// Return the fields of `attribute` ReflectStruct, only keeping the ones named in `reflect_fields` if it's set.
+fn reflect_struct_attributes(attribute : &Attribute, reflect_fields : Option<&[String]>) -> Vec<Attribute>
+{
+  let mut attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
+  if let Some(fields) = reflect_fields
+  {
+    //filter in place to avoid a second allocation
+    attributes.retain(|field| fields.iter().any(|name| name.as_str() == field.name()));
+  }
+  attributes
+}
+
+/**
+ *  Multithreaded function that iterate on `nodes` and return the [Node] which dotted [Attribute] names match `query_value` according to `mode`.
+ *  All the patterns are compiled with `match_method_type` and checked during a single traversal of each node attributes.
+ */
+pub fn match_attribute_name_mode(tree : &Tree, nodes : &Vec<TreeNodeId>, match_method_type : MatchMethod, query_value : &str, mode : &AttributeMatchMode) -> Result<Vec<TreeNodeId>>
+{
+  let mut patterns = vec![(query_value.to_string(), MatcherMethod::new(&match_method_type, query_value)?)];
+  if let AttributeMatchMode::AllPresent(required) = mode
+  {
+    for pattern in required
+    {
+      patterns.push((pattern.clone(), MatcherMethod::new(&match_method_type, pattern)?));
+    }
+  }
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    if let Some(node) = tree.get_node_from_id(*node_id)
+    {
+      let mut found = vec![false; patterns.len()];
+      for attribute in node.value().attributes().iter()
+      {
+        if match_attribute_dotted_name_all("", &attribute, &patterns, &mut found)
+        {
+          return Some(*node_id)
+        }
+      }
+    }
+    None
+  }).collect())
+}
+
+//return true as soon as every patterns matched at least one attribute name
+fn match_attribute_dotted_name_all(dotted_attrib : &str, attribute : &Attribute, patterns : &[(String, MatcherMethod)], found : &mut Vec<bool>) -> bool
+{
+  let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+
+  if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      if match_attribute_dotted_name_all(&dotted_attrib, &current_attribute, patterns, found)
+      {
+        return true
+      }
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
+    for current_attribute in attributes.iter()
+    {
+      if match_attribute_dotted_name_all(&dotted_attrib, &current_attribute, patterns, found)
+      {
+        return true
+      }
+    }
+  }
+
+  for (index, (query, matcher)) in patterns.iter().enumerate()
+  {
+    if !found[index] && matcher.is_match(query, &dotted_attrib)
+    {
+      found[index] = true;
+    }
+  }
+  found.iter().all(|found| *found)
+}
+
+/**
+ *  Same as [match_query] but return one entry for each matching attribute with the dotted name of that attribute,
+ *  so a node with multiple matching attributes is returned multiple times. 
+ *  For [QueryType::Name] the node name is returned and for [QueryType::Path] the node path.
+ *  For [QueryType::AttributeValue] the dotted names of the attributes which value matched are returned.
+ *  `options.reflect_fields`, `options.max_depth` and `options.regex_limits` apply like for [match_query], other options are ignored.
+ */
+pub fn match_query_detailed(tree : &Tree, nodes : &Vec<TreeNodeId>, query_type : QueryType, match_method_type : MatchMethod, query_value : &str, options : &QueryOptions) -> Result<Vec<(TreeNodeId, String)>>
+{
+  let matcher = MatcherMethod::new_with_limits(&match_method_type, query_value, &options.regex_limits)?;
+  let reflect_fields = options.reflect_fields.as_deref();
+
+  Ok(nodes.par_iter().flat_map_iter(|node_id|
+  {
+    let names = match tree.get_node_from_id(*node_id)
+    {
+      Some(node) => match query_type
+      {
+        QueryType::Name => match matcher.is_match(query_value, &node.name())
+        {
+          true => vec![node.name()],
+          false => Vec::new(),
+        },
+        QueryType::Path => tree.node_path(*node_id).filter(|path| matcher.is_match(query_value, path)).into_iter().collect(),
+        QueryType::AttributeName => collect_attributes_dotted_name(&node, query_value, &matcher, reflect_fields, options.max_depth),
+        QueryType::AttributeValue => attributes_dotted_values(&node, reflect_fields, options.max_depth).into_iter()
+                                                                    .filter_map(|(name, value)| matcher.is_match(query_value, &value).then(|| name))
+                                                                    .collect(),
+      },
+      None => Vec::new(),
+    };
+    names.into_iter().map(move |name| (*node_id, name))
+  }).collect())
+}
+
+/**
+ *  Multithreaded function that iterate on `nodes` and return, for each matching [Node], all the dotted [Attribute] names that matched `query_value` using `match_method_type` [MatcherMethod].
+ *  Unlike [match_query] with [QueryType::AttributeName] it doesn't stop on the first matching attribute, so it's slower.
+ */
+pub fn match_attribute_name_detailed(tree : &Tree, nodes : &Vec<TreeNodeId>, match_method_type : MatchMethod, query_value : &str) -> Result<Vec<(TreeNodeId, Vec<String>)>>
+{
+  let matcher = MatcherMethod::new(&match_method_type, query_value)?;
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    if let Some(node) = tree.get_node_from_id(*node_id)
+    {
+      let names = collect_attributes_dotted_name(&node, query_value, &matcher, None, None);
+      if !names.is_empty()
+      {
+        return Some((*node_id, names))
+      }
+    }
+    None
+  }).collect())
+}
+
+fn collect_attributes_dotted_name(node : &Node, query_value : &str, matcher : &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> Vec<String>
+{
+  let mut names = Vec::new();
+  for attribute in node.value().attributes().iter()
+  {
+    collect_attribute_dotted_name("", &attribute, query_value, matcher, reflect_fields, max_depth, &mut names);
+  }
+  names
+}
+
+//max_depth is the number of levels that can still be descended into
+fn collect_attribute_dotted_name(dotted_attrib : &str, attribute : &Attribute, query_value : &str, matcher : &MatcherMethod, reflect_fields : Option<&[String]>, max_depth : Option<usize>, names : &mut Vec<String>)
+{
+  let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+
+  if max_depth == Some(0)
+  {
+    //don't descend, only match this attribute
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      collect_attribute_dotted_name(&dotted_attrib, &current_attribute, query_value, matcher, reflect_fields, max_depth.map(|depth| depth - 1), names);
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    for current_attribute in reflect_struct_attributes(attribute, reflect_fields).iter()
+    {
+      collect_attribute_dotted_name(&dotted_attrib, &current_attribute, query_value, matcher, reflect_fields, max_depth.map(|depth| depth - 1), names);
+    }
+  }
+
+  if matcher.is_match(query_value, &dotted_attrib)
+  {
+    names.push(dotted_attrib);
+  }
+}
+
+/**
+ *  Search a large attribute value line by line using the grep searcher (like [query_data_line](crate::data::query_data_line)).
+ */
+#[cfg_attr(not(feature = "regex"), allow(dead_code))]
+struct LineMatcher
+{
+  #[cfg(feature = "regex")]
+  matcher : RegexMatcher,
+  threshold : usize,
+}
+
+impl LineMatcher
+{
+  /// Return the matcher used to search `value` line by line if it's matched with [MatchMethod::Regex] and `options.line_threshold` is set.
+  #[cfg(feature = "regex")]
+  fn new(value_match_type : &MatchMethod, value : &str, options : &QueryOptions) -> Result<Option<LineMatcher>>
+  {
+    match (value_match_type, options.line_threshold)
+    {
+      (MatchMethod::Regex, Some(threshold)) =>
+      {
+        let matcher = RegexMatcherBuilder::new().size_limit(options.regex_limits.size_limit)
+                                                .dfa_size_limit(options.regex_limits.dfa_size_limit)
+                                                .build(value)?;
+        Ok(Some(LineMatcher{ matcher, threshold }))
+      },
+      _ => Ok(None),
+    }
+  }
+
+  //without the regex feature a LineMatcher is never created as MatchMethod::Regex is rejected
+  #[cfg(not(feature = "regex"))]
+  fn new(_value_match_type : &MatchMethod, _value : &str, _options : &QueryOptions) -> Result<Option<LineMatcher>>
+  {
+    Ok(None)
+  }
+
+  #[cfg(not(feature = "regex"))]
+  fn is_match(&self, _value : &str) -> Result<bool>
+  {
+    Ok(false)
+  }
+
+  /// Return an error if the value can't be searched (a line is longer than the searcher heap limit).
+  #[cfg(feature = "regex")]
+  fn is_match(&self, value : &str) -> Result<bool>
+  {
+    let mut searcher = SearcherBuilder::new().build();
+    let mut found = false;
+
+    let sink = Bytes(|_lnum, _line| {
+      found = true;
+      Ok(false) //stop on first matching line
+    });
+    searcher.search_slice(&self.matcher, value.as_bytes(), sink)?;
+    Ok(found)
+  }
+}
+
+/**
+ *  Match query on a specific attribute `name` on a specific `value` 
+ *  both (name and value) having their specific [MatchMethod] 
+ *  and attribute `name` use the dotted notation 
+ *  attribute:' ' == '' , attribute:w:'' == ''.
+ *  If `options.line_threshold` is set and `value_match_type` is [MatchMethod::Regex], 
+ *  stringified values longer than the threshold are searched line by line with the grep searcher, 
+ *  smaller values (and other match method) use the fast path.
+ *  `options.skip` is ignored, other [QueryOptions] apply like for [match_query].
+ *  Returned ids are sorted and unique, even if `nodes` contains duplicates.
+ **/
+pub fn match_attribute_query(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod, options : &QueryOptions) -> Result<Vec<TreeNodeId>>
+{
+  //We reuse the same matcher in every thread (there should be all multithreadable)
+  let name_matcher = MatcherMethod::new_with_limits(&name_match_type, name, &options.regex_limits)?;
+  let value_matcher = MatcherMethod::new_with_limits(&value_match_type, value, &options.regex_limits)?;
+  let line_matcher = LineMatcher::new(&value_match_type, value, options)?;
+
+  match_attribute_nodes(tree, nodes, name, &name_matcher, value, &value_matcher, line_matcher.as_ref(), options.opaque_values, &AttributeCombine::And, options.reflect_fields.as_deref(), options.max_depth, options.limit)
+}
+
+/**
+ *  Return if the [Node] `node_id` has an attribute which name and value match like [match_attribute_query], without iterating in parallel on a list of nodes.
+ *  Return false if the node can't be found in the [Tree].
+ */
+pub fn match_attribute_node(tree : &Tree, node_id : TreeNodeId, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod) -> Result<bool>
+{
+  let name_matcher = MatcherMethod::new(&name_match_type, name)?;
+  let value_matcher = MatcherMethod::new(&value_match_type, value)?;
+  tree.get_node_from_id(node_id).map_or(Ok(false), |node| match_attribute_name_value(&node, name, &name_matcher, value, &value_matcher, None, false, &AttributeCombine::And, None, None))
+}
+
+/**
+ *  Same as [match_attribute_query] but only return the number of matching nodes, results are not collected.
+ *  Duplicated ids in `nodes` are counted once and `options` apply the same way, so the count is the length of the [match_attribute_query] result.
+ */
+pub fn match_attribute_query_count(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod, options : &QueryOptions) -> Result<usize>
+{
+  let name_matcher = MatcherMethod::new_with_limits(&name_match_type, name, &options.regex_limits)?;
+  let value_matcher = MatcherMethod::new_with_limits(&value_match_type, value, &options.regex_limits)?;
+  let line_matcher = LineMatcher::new(&value_match_type, value, options)?;
+  let unique : HashSet<TreeNodeId> = nodes.iter().copied().collect();
+
+  let matched = unique.par_iter().map(|node_id| match tree.get_node_from_id(*node_id)
+  {
+    Some(node) => match_attribute_name_value(&node, name, &name_matcher, value, &value_matcher, line_matcher.as_ref(), options.opaque_values, &AttributeCombine::And, options.reflect_fields.as_deref(), options.max_depth),
+    None => Ok(false),
+  }.map(usize::from)).sum::<Result<usize>>()?;
+  Ok(options.limit.map_or(matched, |limit| matched.min(limit)))
+}
+
+/**
+ *  Same as [match_attribute_query] but use `name_matcher` and `value_matcher` rather than compiling new ones, so they can be reused between queries.
+ *  `name_matcher` must have been created with `name` and `value_matcher` with `value`, opaque values are not matched.
+ */
+pub fn match_attribute_query_with_matchers(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_matcher : &MatcherMethod, value : &str, value_matcher : &MatcherMethod) -> Result<Vec<TreeNodeId>>
+{
+  match_attribute_nodes(tree, nodes, name, name_matcher, value, value_matcher, None, false, &AttributeCombine::And, None, None, None)
+}
+
+/**
+ *  Same as [match_attribute_query] but return the nodes having an attribute which name match `name` or which value match `value`,
+ *  rather than an attribute matching both, to search a keyword anywhere in the attributes. Opaque values are not matched.
+ */
+pub fn match_attribute_name_or_value(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod) -> Result<Vec<TreeNodeId>>
+{
+  let name_matcher = MatcherMethod::new(&name_match_type, name)?;
+  let value_matcher = MatcherMethod::new(&value_match_type, value)?;
+  match_attribute_nodes(tree, nodes, name, &name_matcher, value, &value_matcher, None, false, &AttributeCombine::Or, None, None, None)
+}
+
+fn match_attribute_nodes(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_matcher : &MatcherMethod, value : &str, value_matcher : &MatcherMethod, line_matcher : Option<&LineMatcher>, opaque_values : bool, combine : &AttributeCombine, reflect_fields : Option<&[String]>, max_depth : Option<usize>, limit : Option<usize>) -> Result<Vec<TreeNodeId>>
+{
+  let limit = ResultLimit::new(limit);
+
+  let mut result : Vec<TreeNodeId> = nodes.par_iter().filter_map(|node_id|
+  {
+    if limit.is_reached()
+    {
+      return None
+    }
+    let node = tree.get_node_from_id(*node_id)?;
+    match match_attribute_name_value(&node, name, name_matcher, value, value_matcher, line_matcher, opaque_values, combine, reflect_fields, max_depth)
+    {
+      Ok(true) =>
+      {
+        limit.found();
+        Some(Ok(*node_id))
+      },
+      Ok(false) => None,
+      Err(err) => Some(Err(err)),
+    }
+  }).collect::<Result<_>>()?;
+
+  result.sort_unstable();
+  result.dedup();
+  limit.truncate(&mut result);
+  Ok(result)
+}
+
+/**
+ *  An [Attribute] that matched a query, returned by [match_attribute_query_detailed].
+ */
+pub struct AttributeMatch
+{
+  /// Dotted name of the attribute.
+  pub name : String,
+  /// Attribute value converted to string.
+  pub value : String,
+  /// Type of the attribute value.
+  pub type_id : ValueTypeId,
+}
+
+/**
+ *  Same as [match_attribute_query] but return for each matching [Node] all the attributes which name and value matched.
+ *  All the attributes of a node are traversed, so it's slower than [match_attribute_query].
+ *  `options.reflect_fields`, `options.max_depth`, `options.opaque_values` and `options.regex_limits` apply like for [match_attribute_query], other options are ignored.
+ */
+pub fn match_attribute_query_detailed(tree: &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, value : &str, value_match_type : MatchMethod, options : &QueryOptions) -> Result<Vec<(TreeNodeId, Vec<AttributeMatch>)>>
+{
+  let name_matcher = MatcherMethod::new_with_limits(&name_match_type, name, &options.regex_limits)?;
+  let value_matcher = MatcherMethod::new_with_limits(&value_match_type, value, &options.regex_limits)?;
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    let mut matches = Vec::new();
+    for attribute in node.value().attributes().iter()
+    {
+      collect_attribute_name_and_value("", &attribute, name, &name_matcher, value, &value_matcher, options, options.max_depth, &mut matches);
+    }
+
+    match matches.is_empty()
+    {
+      true => None,
+      false => Some((*node_id, matches)),
+    }
+  }).collect())
+}
+
+//max_depth is the number of levels that can still be descended into
+fn collect_attribute_name_and_value(dotted_attrib : &str, attribute : &Attribute, query_attr_name : &str, name_matcher : &MatcherMethod, query_attr_value : &str, value_matcher : &MatcherMethod, options : &QueryOptions, max_depth : Option<usize>, matches : &mut Vec<AttributeMatch>)
+{
+  let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+  let is_container = attribute.type_id() == ValueTypeId::Attributes || attribute.type_id() == ValueTypeId::ReflectStruct;
+
+  if is_container && max_depth == Some(0)
+  {
+    //don't descend
+    return
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      collect_attribute_name_and_value(&dotted_attrib, &current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher, options, max_depth.map(|depth| depth - 1), matches);
+    }
+    return
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    for current_attribute in reflect_struct_attributes(attribute, options.reflect_fields.as_deref()).iter()
+    {
+      collect_attribute_name_and_value(&dotted_attrib, &current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher, options, max_depth.map(|depth| depth - 1), matches);
+    }
+    return
+  }
+
+  //only leaf attributes are matched, the value of a container is its stringified children
+  if (options.opaque_values || !is_opaque_value(attribute)) && name_matcher.is_match(query_attr_name, &dotted_attrib)
+  {
+    let value = attribute.value().to_string();
+    if value_matcher.is_match(query_attr_value, &value)
+    {
+      matches.push(AttributeMatch{ name : dotted_attrib, value, type_id : attribute.type_id() });
+    }
+  }
+}
+
+fn match_attribute_name_value(node : &Node, query_attr_name : &str, name_matcher : &MatcherMethod, query_attr_value : &str, value_matcher : &MatcherMethod, line_matcher : Option<&LineMatcher>, opaque_values : bool, combine : &AttributeCombine, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> Result<bool>
+{
+  for attribute in node.value().attributes().iter()
+  {
+    if match_attribute_name_and_value("", &attribute, &query_attr_name, &name_matcher, &query_attr_value, &value_matcher, line_matcher, opaque_values, combine, reflect_fields, max_depth)? 
+    {
+      return Ok(true)
+    }
+  }
+  return Ok(false)
+}
+
+
+//max_depth is the number of levels that can still be descended into,
+//name and value are only matched on leaf attributes, container attributes (Attributes and ReflectStruct) never match themselves
+fn match_attribute_name_and_value(dotted_attrib: &str, attribute: &Attribute, query_attr_name : &str, name_matcher : &MatcherMethod, query_attr_value : &str, value_matcher : &MatcherMethod, line_matcher : Option<&LineMatcher>, opaque_values : bool, combine : &AttributeCombine, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> Result<bool>
+{
+  let is_container = attribute.type_id() == ValueTypeId::Attributes || attribute.type_id() == ValueTypeId::ReflectStruct;
+  if is_container && max_depth == Some(0)
+  {
+    //don't descend
+    return Ok(false);
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+      for current_attribute in attribute.value().as_attributes().attributes().iter()
+      {
+        if match_attribute_name_and_value(&dotted_attrib, current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher, line_matcher, opaque_values, combine, reflect_fields, max_depth.map(|depth| depth - 1))?
+        {
+          return Ok(true);
+        }
+      }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct 
+  { 
+      //we transform it to attributes 
+      let attributes : Vec<Attribute> = reflect_struct_attributes(attribute, reflect_fields); //copied once per visited struct as tap only return owned attributes
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+      for current_attribute in attributes.iter() 
+      {
+        if match_attribute_name_and_value(&dotted_attrib, current_attribute, query_attr_name, name_matcher, query_attr_value, value_matcher, line_matcher, opaque_values, combine, reflect_fields, max_depth.map(|depth| depth - 1))?
+        {
+          return Ok(true);
+        }
+      }
+  }
+  if is_container
+  {
+    return Ok(false);
+  }
+  let name_match = || match dotted_attrib.len() 
+  {
+    0 => name_matcher.is_match(query_attr_name, &attribute.name()),
+    _ => name_matcher.is_match(query_attr_name, &(dotted_attrib.to_string() + "." + attribute.name())),
+  };
+  let value_match = || -> Result<bool> { Ok((opaque_values || !is_opaque_value(attribute)) && 
+                                             match_value(&query_attr_value, value_matcher, line_matcher, &attribute.value().to_string())?) };
+  //the value is only converted to string if needed
+  match combine
+  {
+    AttributeCombine::And => Ok(name_match() && value_match()?),
+    AttributeCombine::Or => Ok(name_match() || value_match()?),
+  }
+}
+
+fn match_value(query_attr_value : &str, value_matcher : &MatcherMethod, line_matcher : Option<&LineMatcher>, value : &str) -> Result<bool>
+{
+  match line_matcher
+  {
+    Some(line_matcher) if value.len() > line_matcher.threshold => line_matcher.is_match(value),
+    _ => Ok(value_matcher.is_match(query_attr_value, value)),
+  }
+}
+
+/**
+ *  Sorted index of dotted [Attribute] names to the [Node] that have them, built by [build_name_index].
+ *  The index keeps a copy of every distinct dotted name and one [TreeNodeId] per (name, node) pair,
+ *  so its memory cost grows with the total number of attributes of the indexed nodes.
+ *  It's a snapshot : it must be rebuilt when nodes or attributes are added to the [Tree].
+ */
+pub struct NameIndex
+{
+  names : BTreeMap<String, Vec<TreeNodeId>>,
+}
+
+impl NameIndex
+{
+  /// Return sorted nodes [Id](TreeNodeId) that have an attribute which dotted name is exactly `name`.
+  pub fn exact(&self, name : &str) -> Vec<TreeNodeId>
+  {
+    match self.names.get(name)
+    {
+      Some(ids) => ids.clone(),
+      None => Vec::new(),
+    }
+  }
+
+  /// Return sorted nodes [Id](TreeNodeId) that have an attribute which dotted name start with `prefix`.
+  pub fn prefix(&self, prefix : &str) -> Vec<TreeNodeId>
+  {
+    let mut result : Vec<TreeNodeId> = self.names.range(prefix.to_string()..)
+                                                 .take_while(|(name, _)| name.starts_with(prefix))
+                                                 .flat_map(|(_, ids)| ids.iter().copied())
+                                                 .collect();
+    result.sort();
+    result.dedup();
+    result
+  }
+}
+
+/// Build a [NameIndex] of all the dotted [Attribute] names of `nodes`.
+pub fn build_name_index(tree : &Tree, nodes : &Vec<TreeNodeId>) -> NameIndex
+{
+  let names_by_node : Vec<(TreeNodeId, Vec<String>)> = nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    Some((*node_id, attributes_dotted_names(&node, None, None)))
+  }).collect();
+
+  let mut names : BTreeMap<String, Vec<TreeNodeId>> = BTreeMap::new();
+  for (node_id, node_names) in names_by_node
+  {
+    for name in node_names
+    {
+      names.entry(name).or_default().push(node_id);
+    }
+  }
+  for ids in names.values_mut()
+  {
+    ids.sort();
+    ids.dedup();
+  }
+  NameIndex{ names }
+}
+
+/**
+ *  Multithreaded function that return the distinct dotted [Attribute] names of `nodes`, sorted.
+ *  If `options.max_depth` is set, nested attributes are not descended into deeper than `max_depth` levels (0 only return first-level attributes),
+ *  if `options.reflect_fields` is set only these ReflectStruct fields are returned and descended into. Other options are ignored.
+ */
+pub fn collect_attribute_names(tree : &Tree, nodes : &Vec<TreeNodeId>, options : &QueryOptions) -> BTreeSet<String>
+{
+  nodes.par_iter().filter_map(|node_id| tree.get_node_from_id(*node_id))
+                  .fold(BTreeSet::new, |mut names, node|
+                  {
+                    names.extend(attributes_dotted_names(&node, options.reflect_fields.as_deref(), options.max_depth));
+                    names
+                  })
+                  .reduce(BTreeSet::new, |mut names, other|
+                  {
+                    names.extend(other);
+                    names
+                  })
+}
+
+/// Return all the dotted [Attribute] names of `node`, down to `max_depth` levels if it's set and only in the `reflect_fields` ReflectStruct fields if it's set.
+fn attributes_dotted_names(node : &Node, reflect_fields : Option<&[String]>, max_depth : Option<usize>) -> Vec<String>
+{
+  let mut names = Vec::new();
+  for attribute in node.value().attributes().iter()
+  {
+    attribute_dotted_names("", &attribute, reflect_fields, max_depth, &mut names);
+  }
+  names
+}
+
+//max_depth is the number of levels that can still be descended into
+fn attribute_dotted_names(dotted_attrib : &str, attribute : &Attribute, reflect_fields : Option<&[String]>, max_depth : Option<usize>, names : &mut Vec<String>)
+{
+  let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+
+  if max_depth == Some(0)
+  {
+    //don't descend, only add this attribute
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      attribute_dotted_names(&dotted_attrib, &current_attribute, reflect_fields, max_depth.map(|depth| depth - 1), names);
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    for current_attribute in reflect_struct_attributes(attribute, reflect_fields).iter()
+    {
+      attribute_dotted_names(&dotted_attrib, &current_attribute, reflect_fields, max_depth.map(|depth| depth - 1), names);
+    }
+  }
+  names.push(dotted_attrib);
+}
+
+/**
+ *  Range of numeric or [DateTime] values used by [match_attribute_range], a missing bound means the range is open on that side.
+ */
+enum ValueRange
+{
+  Number(Option<f64>, Option<f64>),
+  Time(Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+}
+
+enum RangeBound
+{
+  Number(f64),
+  Time(DateTime<Utc>),
+}
+
+impl RangeBound
+{
+  /// Parse a number, an ISO-8601 date (at midnight UTC) or an RFC 3339 date time.
+  fn parse(bound : &str) -> Result<RangeBound>
+  {
+    if let Ok(number) = bound.parse::<f64>()
+    {
+      return Ok(RangeBound::Number(number))
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(bound)
+    {
+      return Ok(RangeBound::Time(time.with_timezone(&Utc)))
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(bound, "%Y-%m-%d")
+    {
+      return Ok(RangeBound::Time(DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc)))
+    }
+    Err(anyhow!("Invalid range bound {}", bound))
+  }
+}
+
+impl ValueRange
+{
+  fn new(min : Option<&str>, max : Option<&str>) -> Result<ValueRange>
+  {
+    let min = min.map(RangeBound::parse).transpose()?;
+    let max = max.map(RangeBound::parse).transpose()?;
+
+    match (min, max)
+    {
+      (None, None) => Err(anyhow!("Range must have at least one bound")),
+      (Some(RangeBound::Number(min)), Some(RangeBound::Number(max))) if min > max => Err(anyhow!("Range min {} is greater than max {}", min, max)),
+      (Some(RangeBound::Time(min)), Some(RangeBound::Time(max))) if min > max => Err(anyhow!("Range min {} is greater than max {}", min, max)),
+      (Some(RangeBound::Number(min)), Some(RangeBound::Number(max))) => Ok(ValueRange::Number(Some(min), Some(max))),
+      (Some(RangeBound::Time(min)), Some(RangeBound::Time(max))) => Ok(ValueRange::Time(Some(min), Some(max))),
+      (Some(RangeBound::Number(min)), None) => Ok(ValueRange::Number(Some(min), None)),
+      (None, Some(RangeBound::Number(max))) => Ok(ValueRange::Number(None, Some(max))),
+      (Some(RangeBound::Time(min)), None) => Ok(ValueRange::Time(Some(min), None)),
+      (None, Some(RangeBound::Time(max))) => Ok(ValueRange::Time(None, Some(max))),
+      _ => Err(anyhow!("Range bounds must be both numbers or both dates")),
+    }
+  }
+
+  fn contains(&self, attribute : &Attribute) -> bool
+  {
+    match self
+    {
+      ValueRange::Time(min, max) =>
+      {
+        if attribute.type_id() != ValueTypeId::DateTime
+        {
+          return false
+        }
+        let time = attribute.value().as_date_time();
+        min.map_or(true, |min| time >= min) && max.map_or(true, |max| time <= max)
+      },
+      ValueRange::Number(min, max) =>
+      {
+        match numeric_value(attribute)
+        {
+          Some(number) => min.map_or(true, |min| number >= min) && max.map_or(true, |max| number <= max),
+          None => false,
+        }
+      },
+    }
+  }
+}
+
+/// Return the value of `attribute` as a number, or `None` if it's not a numeric value.
+/// Only integer and float values are converted, strings containing a number are not numeric values.
+fn numeric_value(attribute : &Attribute) -> Option<f64>
+{
+  let value = attribute.value();
+  match attribute.type_id()
+  {
+    ValueTypeId::U8 => Some(value.as_u8() as f64),
+    ValueTypeId::U16 => Some(value.as_u16() as f64),
+    ValueTypeId::U32 => Some(value.as_u32() as f64),
+    ValueTypeId::U64 => Some(value.as_u64() as f64),
+    ValueTypeId::I8 => Some(value.as_i8() as f64),
+    ValueTypeId::I16 => Some(value.as_i16() as f64),
+    ValueTypeId::I32 => Some(value.as_i32() as f64),
+    ValueTypeId::I64 => Some(value.as_i64() as f64),
+    ValueTypeId::F32 => Some(value.as_f32() as f64),
+    ValueTypeId::F64 => Some(value.as_f64()),
+    _ => None,
+  }
+}
+
+/**
+ *  Comparison used by [match_attribute_numeric] between an attribute value and the query.
+ */
+#[derive(Debug, Clone)]
+pub enum ValuePredicate
+{
+  Greater,
+  GreaterOrEqual,
+  Less,
+  LessOrEqual,
+  Equal,
+}
+
+impl ValuePredicate
+{
+  /// Return true if `value` compared to `query` satisfy the predicate.
+  pub fn is_match(&self, value : f64, query : f64) -> bool
+  {
+    match self
+    {
+      ValuePredicate::Greater => value > query,
+      ValuePredicate::GreaterOrEqual => value >= query,
+      ValuePredicate::Less => value < query,
+      ValuePredicate::LessOrEqual => value <= query,
+      ValuePredicate::Equal => value == query,
+    }
+  }
+}
+
+/**
+ *  Multithreaded function that return the `nodes` having a numeric attribute which dotted name match `name` using `name_match_type` 
+ *  and which value compared to `value` satisfy `predicate`.
+ *  Attributes that are not numeric never match, an error is only returned if `value` is not a number.
+ *  attribute:'size' > 1048576.
+ */
+pub fn match_attribute_numeric(tree : &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, predicate : ValuePredicate, value : &str) -> Result<Vec<TreeNodeId>>
+{
+  let query = value.parse::<f64>().map_err(|_| anyhow!("Invalid number {}", value))?;
+  let name_matcher = MatcherMethod::new(&name_match_type, name)?;
+  let is_match = |attribute : &Attribute| numeric_value(attribute).map_or(false, |number| predicate.is_match(number, query));
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    for attribute in node.value().attributes().iter()
+    {
+      if match_attribute_name_predicate("", &attribute, name, &name_matcher, &is_match)
+      {
+        return Some(*node_id)
+      }
+    }
+    None
+  }).collect())
+}
+
+/**
+ *  Multithreaded function that return the `nodes` having an attribute which dotted name match `name` using `name_match_type` 
+ *  and which value is included between `min` and `max`.
+ *  Bounds are either numbers, matched against numeric values, or ISO-8601 dates, matched against [DateTime] values. 
+ *  One of the bounds can be omitted for an open range, an error is returned if bounds are invalid or if `min` is greater than `max`.
+ *  attribute:'size'[1024..4096], attribute:w'*.modified'[2021-01-01..].
+ */
+pub fn match_attribute_range(tree : &Tree, nodes : &Vec<TreeNodeId>, name : &str, name_match_type : MatchMethod, min : Option<&str>, max : Option<&str>) -> Result<Vec<TreeNodeId>>
+{
+  let range = ValueRange::new(min, max)?;
+  let name_matcher = MatcherMethod::new(&name_match_type, name)?;
+  let is_match = |attribute : &Attribute| range.contains(attribute);
+
+  Ok(nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    for attribute in node.value().attributes().iter()
+    {
+      if match_attribute_name_predicate("", &attribute, name, &name_matcher, &is_match)
+      {
+        return Some(*node_id)
+      }
+    }
+    None
+  }).collect())
+}
+
+//return true if an attribute which dotted name match is found and `predicate` return true for it
+fn match_attribute_name_predicate(dotted_attrib : &str, attribute : &Attribute, query_attr_name : &str, name_matcher : &MatcherMethod, predicate : &dyn Fn(&Attribute) -> bool) -> bool
+{
+  let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
+
+  if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      if match_attribute_name_predicate(&dotted_attrib, &current_attribute, query_attr_name, name_matcher, predicate)
+      {
+        return true
+      }
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
+    for current_attribute in attributes.iter()
+    {
+      if match_attribute_name_predicate(&dotted_attrib, &current_attribute, query_attr_name, name_matcher, predicate)
+      {
+        return true
+      }
+    }
+  }
+
+  predicate(attribute) && name_matcher.is_match(query_attr_name, &dotted_attrib)
+}
+
+/// Count attributes recursively.
+fn attributes_count_rec(value: &Value) -> u64
+{
+  let mut counter = 0;
+  if value.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in value.as_attributes().attributes().iter()
+    {
+      counter += attributes_count_rec(&current_attribute.value());
+    }
+  }
+  else if value.type_id() == ValueTypeId::ReflectStruct 
+  { 
+    for attribute in value.as_reflect_struct().attributes().iter()
+    {
+      counter += attributes_count_rec(&attribute.value());
+    }
+  }
+  else
+  {
+    counter = 1;
+  }
+  return counter;
+}
+
+/// Count attributes for all [Node] in the [Tree].
+pub fn attribute_count(tree : &Tree) -> u64
+{
+  let nodes = tree.children_rec(None).unwrap();
+  nodes.par_iter().map(|node_id|
+  {
+    if let Some(node) = tree.get_node_from_id(*node_id)
+    {
+      let mut counter = 0;
+      for attribute in node.value().attributes().iter()
+      {
+        counter += attributes_count_rec(&attribute.value());
+      }
+      return counter;
+    }
+    0 
+
+  }).sum()
+}
+
+/// Return true if the [Tree] has at least `threshold` attributes, counting stop as soon as the threshold is reached.
+pub fn attribute_count_at_least(tree : &Tree, threshold : u64) -> bool
+{
+  if threshold == 0
+  {
+    return true
+  }
+  let nodes = tree.children_rec(None).unwrap();
+  let total = AtomicU64::new(0);
+  nodes.par_iter().any(|node_id|
+  {
+    if let Some(node) = tree.get_node_from_id(*node_id)
+    {
+      let mut counter = 0;
+      for attribute in node.value().attributes().iter()
+      {
+        counter += attributes_count_rec(&attribute.value());
+      }
+      return total.fetch_add(counter, Ordering::Relaxed) + counter >= threshold
+    }
+    false
+  })
+}
+
+/// Same as [attribute_count] but run inside `pool` rather than the global rayon thread pool.
+pub fn attribute_count_in_pool(pool : &ThreadPool, tree : &Tree) -> u64
+{
+  pool.install(|| attribute_count(tree))
+}
+
+/// Count attributes recursively for each of `nodes`, nodes that can't be found are omitted.
+pub fn attribute_count_per_node(tree : &Tree, nodes : &Vec<TreeNodeId>) -> Vec<(TreeNodeId, u64)>
+{
+  nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    let mut counter = 0;
+    for attribute in node.value().attributes().iter()
+    {
+      counter += attributes_count_rec(&attribute.value());
+    }
+    Some((*node_id, counter))
+  }).collect()
+}
+
+/**
+ * Multithread function that search all [Node] in the tree and return the one that have an [Attribute] of type [ValueTypeId::VFileBuilder],
+ * nested attributes are searched recursively.
+ */
+pub fn find_vfiles(tree : &Tree) -> Vec<TreeNodeId>
+{
+  let nodes = tree.children_rec(None).unwrap();
+  find_vfiles_in(tree, &nodes, true)
+}
+
+/**
+ * Multithread function that return the `nodes` that have an [Attribute] of type [ValueTypeId::VFileBuilder],
+ * nested attributes are only searched if `recursive` is true.
+ */
+pub fn find_vfiles_in(tree : &Tree, nodes : &[TreeNodeId], recursive : bool) -> Vec<TreeNodeId>
+{
+  match_type_nodes(tree, nodes, &ValueTypeId::VFileBuilder, recursive)
+}
+
+/**
+ * Multithread function that return the `nodes` that have an [Attribute] with exactly the dotted name `dotted_name` (`exif.primary.model`),
+ * only the attributes on the path of `dotted_name` are visited and each node stop on the first match.
+ */
+pub fn has_attribute(tree : &Tree, nodes : &Vec<TreeNodeId>, dotted_name : &str) -> Vec<TreeNodeId>
+{
+  let names : Vec<&str> = dotted_name.split('.').collect();
+
+  nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    let found = node.value().attributes().iter().any(|attribute| has_attribute_rec(attribute, &names));
+    found.then(|| *node_id)
+  }).collect()
+}
+
+/// Return true if `attribute` is named `names[0]` and has nested attributes named after the rest of `names`.
+fn has_attribute_rec(attribute : &Attribute, names : &[&str]) -> bool
+{
+  let names = match names.split_first()
+  {
+    Some((name, names)) if attribute.name() == *name => names,
+    _ => return false,
+  };
+
+  if names.is_empty()
+  {
+    true
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+    attribute.value().as_attributes().attributes().iter().any(|current_attribute| has_attribute_rec(current_attribute, names))
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct
+  {
+    attribute.value().as_reflect_struct().attributes().iter().any(|current_attribute| has_attribute_rec(current_attribute, names))
+  }
+  else
+  {
+    false
+  }
+}
+
+/**
+ * Multithread function that return the `nodes` that have an [Attribute] of type `type_id`,
+ * nested attributes are searched recursively.
+ */
+pub fn match_attribute_type(tree : &Tree, nodes : &Vec<TreeNodeId>, type_id : ValueTypeId) -> Vec<TreeNodeId>
+{
+  match_type_nodes(tree, nodes, &type_id, true)
+}
+
+fn match_type_nodes(tree : &Tree, nodes : &[TreeNodeId], type_id : &ValueTypeId, recursive : bool) -> Vec<TreeNodeId>
+{
+  match_type_nodes_where(tree, nodes, type_id, recursive, &|_| true)
+}
+
+/// Return the `nodes` that have an [Attribute] of type `type_id` for which `predicate` return true, 
+/// nested attributes are only searched if `recursive` is true.
+pub(crate) fn match_type_nodes_where(tree : &Tree, nodes : &[TreeNodeId], type_id : &ValueTypeId, recursive : bool, predicate : &(dyn Fn(&Attribute) -> bool + Sync)) -> Vec<TreeNodeId>
 {
-  //XXX pass node list
-  let nodes = tree.children_rec(None).unwrap();
   nodes.par_iter().filter_map(|node_id|
   {
     if let Some(node) = tree.get_node_from_id(*node_id)
     {
       for attribute in node.value().attributes().iter()
       {
-        if attribute.type_id() == ValueTypeId::VFileBuilder
+        let has_type = match recursive
+        {
+          true => has_type_rec(&attribute, type_id, predicate),
+          false => attribute.type_id() == *type_id && predicate(&attribute),
+        };
+        if has_type
         {
           return Some(*node_id) 
         }
@@ -308,3 +1751,230 @@ pub fn find_vfiles(tree : &Tree) -> Vec<TreeNodeId>
     None
   }).collect()
 }
+
+/// Return true if `attribute` or one of its nested attributes is of type `type_id` and `predicate` return true for it.
+fn has_type_rec(attribute : &Attribute, type_id : &ValueTypeId, predicate : &dyn Fn(&Attribute) -> bool) -> bool
+{
+  if attribute.type_id() == *type_id && predicate(attribute)
+  {
+    return true
+  }
+  else if attribute.type_id() == ValueTypeId::Attributes
+  {
+    for current_attribute in attribute.value().as_attributes().attributes().iter()
+    {
+      if has_type_rec(&current_attribute, type_id, predicate)
+      {
+        return true
+      }
+    }
+  }
+  else if attribute.type_id() == ValueTypeId::ReflectStruct 
+  {
+    for current_attribute in attribute.value().as_reflect_struct().attributes().iter()
+    {
+      if has_type_rec(&current_attribute, type_id, predicate)
+      {
+        return true
+      }
+    }
+  }
+  false
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use tap::attribute::Attributes;
+  use std::sync::Arc;
+
+  /// Return a tree with `count` nodes named `node<index>` having an `index` attribute and a nested `header.index` attribute.
+  fn test_tree(count : usize) -> (Tree, Vec<TreeNodeId>)
+  {
+    let tree = Tree::new();
+    for index in 0..count
+    {
+      let node = Node::new(format!("node{}", index));
+      node.value().add_attribute("index", index as u64, None);
+      let header = Attributes::new();
+      header.add_attribute("index", index as u64, None);
+      node.value().add_attribute("header", Value::Attributes(Arc::new(header)), None);
+      tree.add_child(tree.root_id, node).unwrap();
+    }
+    let nodes = tree.children_rec(None).unwrap();
+    (tree, nodes)
+  }
+
+  /// Return `nodes` in reverse order with each node twice.
+  fn duplicated_reversed(nodes : &[TreeNodeId]) -> Vec<TreeNodeId>
+  {
+    nodes.iter().rev().chain(nodes.iter()).copied().collect()
+  }
+
+  fn is_sorted_unique(ids : &[TreeNodeId]) -> bool
+  {
+    ids.windows(2).all(|pair| pair[0] < pair[1])
+  }
+
+  #[test]
+  fn nested_name_and_value_only_match_leaves()
+  {
+    let tree = Tree::new();
+    let node = Node::new("file");
+    let exif = Attributes::new();
+    exif.add_attribute("make", "Canon", None);
+    exif.add_attribute("model", "EOS", None);
+    let header = Attributes::new();
+    header.add_attribute("exif", Value::Attributes(Arc::new(exif)), None);
+    node.value().add_attribute("header", Value::Attributes(Arc::new(header)), None);
+    let node_id = tree.add_child(tree.root_id, node).unwrap();
+    let nodes = vec![node_id];
+    let options = QueryOptions::default();
+    let query = |name : &str, value : &str| match_attribute_query(&tree, &nodes, name, MatchMethod::Wildcard, value, MatchMethod::Wildcard, &options).unwrap();
+
+    assert_eq!(query("header.exif.make", "Canon"), nodes);
+    assert_eq!(query("header.*", "EOS"), nodes);
+    //containers are not matched with their stringified children (ReflectStruct containers are handled the same way)
+    assert!(query("header", "*Canon*").is_empty());
+    assert!(query("header.exif", "*EOS*").is_empty());
+    //name and value must match on the same leaf
+    assert!(query("header.exif.make", "EOS").is_empty());
+    assert_eq!(query_max_depth(&tree, &nodes, Some(1)), Vec::<TreeNodeId>::new());
+    assert_eq!(query_max_depth(&tree, &nodes, Some(2)), nodes);
+  }
+
+  fn query_max_depth(tree : &Tree, nodes : &Vec<TreeNodeId>, max_depth : Option<usize>) -> Vec<TreeNodeId>
+  {
+    let options = QueryOptions{ max_depth, ..Default::default() };
+    match_attribute_query(tree, nodes, "*make", MatchMethod::Wildcard, "Canon", MatchMethod::Fixed, &options).unwrap()
+  }
+
+  #[test]
+  fn match_results_sorted_unique()
+  {
+    let (tree, nodes) = test_tree(100);
+    let duplicated = duplicated_reversed(&nodes);
+    let options = QueryOptions::default();
+
+    //every node match twice, by its name and in both index attributes
+    let result = match_query(&tree, &duplicated, QueryType::Name, MatchMethod::Wildcard, "node*", &options).unwrap();
+    assert_eq!(result.len(), 100);
+    assert!(is_sorted_unique(&result));
+
+    let result = match_query(&tree, &duplicated, QueryType::AttributeName, MatchMethod::EndsWith, "index", &options).unwrap();
+    assert_eq!(result.len(), 100);
+    assert!(is_sorted_unique(&result));
+
+    let result = match_attribute_query(&tree, &duplicated, "*index", MatchMethod::Wildcard, "1*", MatchMethod::Wildcard, &options).unwrap();
+    assert_eq!(result.len(), 11);
+    assert!(is_sorted_unique(&result));
+  }
+
+  #[test]
+  fn count_match_results_len()
+  {
+    let (tree, nodes) = test_tree(100);
+    let duplicated = duplicated_reversed(&nodes);
+
+    for options in [QueryOptions::default(), QueryOptions{ limit : Some(5), ..Default::default() }, QueryOptions{ max_depth : Some(0), ..Default::default() }]
+    {
+      let result = match_query(&tree, &duplicated, QueryType::AttributeName, MatchMethod::EndsWith, "index", &options).unwrap();
+      assert_eq!(match_query_count(&tree, &duplicated, QueryType::AttributeName, MatchMethod::EndsWith, "index", &options).unwrap(), result.len());
+
+      let result = match_attribute_query(&tree, &duplicated, "*index", MatchMethod::Wildcard, "1*", MatchMethod::Wildcard, &options).unwrap();
+      assert_eq!(match_attribute_query_count(&tree, &duplicated, "*index", MatchMethod::Wildcard, "1*", MatchMethod::Wildcard, &options).unwrap(), result.len());
+    }
+  }
+
+  #[test]
+  fn numeric_predicates_ignore_strings()
+  {
+    let tree = Tree::new();
+    let add_node = |name : &str, size : Value|
+    {
+      let node = Node::new(name);
+      node.value().add_attribute("size", size, None);
+      tree.add_child(tree.root_id, node).unwrap()
+    };
+    let number = add_node("number", Value::U32(42));
+    let float = add_node("float", Value::F64(1e3));
+    add_node("string", Value::from("42"));
+    add_node("exponent", Value::from("1e3"));
+    let nodes = tree.children_rec(None).unwrap();
+
+    assert_eq!(match_attribute_numeric(&tree, &nodes, "size", MatchMethod::Fixed, ValuePredicate::Greater, "10").unwrap(), vec![number, float]);
+    assert_eq!(match_attribute_range(&tree, &nodes, "size", MatchMethod::Fixed, Some("10"), Some("100")).unwrap(), vec![number]);
+  }
+
+  #[test]
+  fn detailed_traversals_apply_options()
+  {
+    let (tree, nodes) = test_tree(10);
+    let first_level = QueryOptions{ max_depth : Some(0), ..Default::default() };
+    let names = |options : &QueryOptions| collect_attribute_names(&tree, &nodes, options).into_iter().collect::<Vec<String>>();
+    assert_eq!(names(&QueryOptions::default()), vec!["header", "header.index", "index"]);
+    assert_eq!(names(&first_level), vec!["header", "index"]);
+
+    let matched = match_attribute_query_detailed(&tree, &nodes, "*index", MatchMethod::Wildcard, "1", MatchMethod::Fixed, &first_level).unwrap();
+    assert_eq!(matched.len(), 1);
+    assert_eq!(matched[0].1.iter().map(|found| found.name.as_str()).collect::<Vec<_>>(), vec!["index"]);
+    let matched = match_query_detailed(&tree, &nodes, QueryType::AttributeValue, MatchMethod::Fixed, "1", &QueryOptions::default()).unwrap();
+    let mut names : Vec<&str> = matched.iter().map(|(_, name)| name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["header.index", "index"]);
+  }
+
+  #[test]
+  fn smart_case_literal_backslash()
+  {
+    let tree = Tree::new();
+    let path = tree.add_child(tree.root_id, Node::new(r"C:\Users\Bob\ntuser.dat")).unwrap();
+    let lower = tree.add_child(tree.root_id, Node::new(r"c:\users\bob\ntuser.dat")).unwrap();
+    let nodes = vec![path, lower];
+    let options = QueryOptions::default();
+    let query = |method : MatchMethod, value : &str| match_query(&tree, &nodes, QueryType::Name, method, value, &options).unwrap();
+
+    //`\U` is not an escape in a literal query, the uppercase letters make it case sensitive
+    assert_eq!(query(MatchMethod::SmartCase, r"C:\Users\Bob\ntuser.dat"), vec![path]);
+    assert_eq!(query(MatchMethod::SmartCase, r"c:\users\bob\ntuser.dat"), nodes);
+    assert_eq!(query(MatchMethod::SmartCaseContains, r"C:\Users\Bob"), vec![path]);
+    assert_eq!(query(MatchMethod::SmartCaseContains, r"\users\bob"), nodes);
+    assert!(query(MatchMethod::SmartCaseContains, r"\Users\Alice").is_empty());
+  }
+
+  #[test]
+  #[cfg(feature = "regex")]
+  fn path_regex_match_node_path()
+  {
+    let tree = Tree::new();
+    for dir_index in 0..3
+    {
+      let dir = tree.add_child(tree.root_id, Node::new(format!("dir{}", dir_index))).unwrap();
+      let sub = tree.add_child(dir, Node::new("sub")).unwrap();
+      for file_index in 0..5
+      {
+        tree.add_child(sub, Node::new(format!("file{}.txt", file_index))).unwrap();
+      }
+    }
+    let nodes = tree.children_rec(None).unwrap();
+
+    for pattern in ["/dir1/sub/file[0-2]\\.txt$", "/sub$", "dir"]
+    {
+      let regex = Regex::new(pattern).unwrap();
+      let expected : Vec<TreeNodeId> = nodes.iter().filter(|node_id| regex.is_match(&tree.node_path(**node_id).unwrap())).copied().collect();
+      assert!(!expected.is_empty());
+      assert_eq!(match_path_regex(&tree, &nodes, pattern).unwrap(), expected, "pattern {}", pattern);
+    }
+  }
+
+  #[test]
+  fn cancelled_query_error()
+  {
+    let (tree, nodes) = test_tree(10);
+    let cancel = AtomicBool::new(true);
+
+    let err = match_query_cancellable(&tree, &nodes, QueryType::Name, MatchMethod::Wildcard, "node*", &cancel).unwrap_err();
+    assert!(err.downcast_ref::<QueryCancelled>().is_some());
+  }
+}
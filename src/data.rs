@@ -1,166 +1,1729 @@
 //! Method and Struct to search in [Node] [VFile](tap::vfile::VFile) Data [tap::attribute::Attribute].
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::cmp::Ordering;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize};
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
 use tap::tree::{Tree, TreeNodeId};
 use tap::node::Node;
+use tap::attribute::Attribute;
+use tap::value::ValueTypeId;
+use tap::vfile::VFile;
 
 use rayon::prelude::*;
+use rayon::ThreadPool;
 use grep_matcher::Matcher;
-use grep_regex::RegexMatcher;
-use grep_searcher::SearcherBuilder;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{Encoding, Searcher, SearcherBuilder, Sink, SinkMatch};
 use grep_searcher::sinks::Bytes;
-use regex::bytes::RegexBuilder;
-use anyhow::Result;
+use regex::bytes::{RegexBuilder, RegexSet, RegexSetBuilder};
+use aho_corasick::AhoCorasick;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+
+use crate::progress::{poll_progress, ResultLimit};
+pub use crate::progress::QueryCancelled;
+use crate::attribute::{RegexLimits, regex_error, match_type_nodes_where};
 
 /**
  *  Method to search in [Node] data Attribute content. 
  */
+#[derive(Debug, Clone)]
 pub enum DataMethod
 {
   /// Search raw data using regexp.
   Regex,
   /// Search Unicode 8 or Unicode 16 text line by line using regexp.
   Text,
+  /// Search Unicode 8 or Unicode 16 text line by line for the literal query, regex special characters don't need to be escaped.
+  TextLiteral,
+}
+
+impl DataMethod
+{
+  /// Return the regex searched line by line for `query_value`, escaped for [DataMethod::TextLiteral].
+  fn line_pattern<'a>(&self, query_value : &'a str) -> Cow<'a, str>
+  {
+    match self
+    {
+      DataMethod::TextLiteral => Cow::Owned(regex::escape(query_value)),
+      _ => Cow::Borrowed(query_value),
+    }
+  }
+}
+
+/**
+ *  Encoding of the data searched with [DataMethod::Text].
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding
+{
+  /// Search UTF-8, UTF-16 files are only decoded when they start with a BOM.
+  Auto,
+  /// Decode data as UTF-16 little endian before searching (unless a BOM says otherwise).
+  Utf16Le,
+  /// Decode data as UTF-16 big endian before searching (unless a BOM says otherwise).
+  Utf16Be,
+}
+
+/**
+ *  Options used when searching in [Node] data.
+ */
+#[derive(Debug, Clone)]
+pub struct DataSearchOptions
+{
+  /// Size of each read when searching with [DataMethod::Regex], the search return an error if it's 0.
+  pub buffer_size : usize,
+  /// Maximum size of a line when searching with [DataMethod::Text], the searcher allocate up to this size for each file.
+  pub heap_limit : usize,
+  /// Files bigger than this size are skipped without being opened.
+  pub max_file_size : Option<u64>,
+  /// Name of the [Node] attribute containing the data to search, nodes without this attribute are skipped.
+  pub attribute_name : String,
+  /// Match case sensitively, if not set [DataMethod::Regex] is case insensitive and [DataMethod::Text] is case sensitive.
+  pub case_sensitive : Option<bool>,
+  /// Match case sensitively only if the query contains an uppercase letter which is not escaped, override `case_sensitive`.
+  pub smart_case : bool,
+  /// Encoding of the data searched with [DataMethod::Text], data is transcoded to UTF-8 before matching.
+  pub encoding : TextEncoding,
+  /// Only match whole words by surrounding the query with `\b`, ASCII word boundaries are used for [DataMethod::Regex] as data can be binary.
+  pub whole_word : bool,
+  /// Stop searching once `limit` nodes matched, at most `limit` nodes are returned but they're not necessarily the first matching ones.
+  pub limit : Option<usize>,
+  /// Only match [DataMethod::Regex] at the start of the data (like a magic number), 
+  /// only the first `buffer_size` bytes (plus the overlap kept between reads) are searched. Ignored for [DataMethod::Text].
+  pub anchored : bool,
+  /// Limits used when compiling the query regex, an error is returned before searching if they're exceeded.
+  pub regex_limits : RegexLimits,
+}
+
+impl DataSearchOptions
+{
+  /// Return if `query_value` must be matched case sensitively, `default` is used if neither `smart_case` nor `case_sensitive` are set.
+  fn is_case_sensitive(&self, query_value : &str, default : bool) -> bool
+  {
+    match self.smart_case
+    {
+      true => has_uppercase_literal(query_value),
+      false => self.case_sensitive.unwrap_or(default),
+    }
+  }
+
+  /// Return the pattern to compile for `query_value`, wrapped in word boundaries if `whole_word` is set.
+  /// The query is wrapped in a group so its inline flags (like `(?-u)`) don't apply to the boundaries, 
+  /// which are Unicode aware only if `unicode` is true as binary data is not valid UTF-8.
+  fn pattern<'a>(&self, query_value : &'a str, unicode : bool) -> Cow<'a, str>
+  {
+    match (self.whole_word, unicode)
+    {
+      (false, _) => Cow::Borrowed(query_value),
+      (true, true) => Cow::Owned(format!("\\b(?:{})\\b", query_value)),
+      (true, false) => Cow::Owned(format!("(?-u:\\b)(?:{})(?-u:\\b)", query_value)),
+    }
+  }
+
+  /// Return the pattern to compile for `query_value` searched with [DataMethod::Regex], anchored at the start of the data if `anchored` is set.
+  fn regex_pattern<'a>(&self, query_value : &'a str) -> Cow<'a, str>
+  {
+    match self.anchored
+    {
+      true => Cow::Owned(format!("\\A(?:{})", self.pattern(query_value, false))),
+      false => self.pattern(query_value, false),
+    }
+  }
+}
+
+impl Default for DataSearchOptions
+{
+  fn default() -> Self
+  {
+    DataSearchOptions{ buffer_size : 4096, heap_limit : 1024*1024*100, max_file_size : None, attribute_name : "data".into(), case_sensitive : None, smart_case : false, encoding : TextEncoding::Auto, whole_word : false, limit : None, anchored : false, regex_limits : RegexLimits::default() }
+  }
+}
+
+/// Return if `pattern` contains an uppercase letter which is not escaped, used for smart case like ripgrep.
+/// Escapes like `\S` or `\W` and the arguments of `\pL`, `\p{Lu}` or `\xAB` are ignored.
+fn has_uppercase_literal(pattern : &str) -> bool
+{
+  let mut chars = pattern.chars().peekable();
+  while let Some(c) = chars.next()
+  {
+    if c != '\\'
+    {
+      if c.is_uppercase()
+      {
+        return true
+      }
+      continue
+    }
+    //number of chars of the escape argument if it's not between braces
+    let argument_len = match chars.next()
+    {
+      Some('p') | Some('P') => 1,
+      Some('x') => 2,
+      Some('u') => 4,
+      Some('U') => 8,
+      _ => continue,
+    };
+    match chars.peek() == Some(&'{')
+    {
+      true => { chars.by_ref().find(|c| *c == '}'); },
+      false => { chars.by_ref().take(argument_len).for_each(drop); },
+    }
+  }
+  false
+}
+
+/// Search in `nodes` data using `data_method` and `options`, nodes in `skip` are removed before searching so their data is never opened.
+/// Each call open the data of every searched node again, opened readers are not kept between calls as they keep a read position 
+/// and can't be shared between the rayon workers. To search several patterns in the same nodes use [query_data_multi] that read each file only once.
+pub fn query_data(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, skip : &HashSet<TreeNodeId>, options : &DataSearchOptions) -> Result<Vec<TreeNodeId>> 
+{
+  let nodes : Vec<TreeNodeId> = nodes.iter().filter(|node_id| !skip.contains(node_id)).copied().collect();
+
+  match data_method
+  {
+    DataMethod::Regex => search_data_regex(tree, &nodes, query_value, options, SearchContext::default()), 
+    DataMethod::Text | DataMethod::TextLiteral =>  search_data_line(tree, &nodes, &data_method.line_pattern(query_value), options, SearchContext::default()),
+  }
+}
+
+/// Same as [query_data] but run inside `pool` rather than the global rayon thread pool, so the number of threads used by the search can be bounded.
+pub fn query_data_in_pool(pool : &ThreadPool, tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, skip : &HashSet<TreeNodeId>, options : &DataSearchOptions) -> Result<Vec<TreeNodeId>>
+{
+  pool.install(|| query_data(tree, nodes, query_value, data_method, skip, options))
+}
+
+/**
+ *  Search in `nodes` data using `data_method` and `options` like [query_data] but call `on_match` with each matching node as soon as it's found,
+ *  rather than returning all the matching nodes once the search is finished. 
+ *  `on_match` is called from the rayon workers, so nodes are not in any particular order.
+ */
+pub fn query_data_callback<F : Fn(TreeNodeId) + Sync>(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions, on_match : F) -> Result<()>
+{
+  let context = SearchContext{ on_match : Some(&on_match), ..Default::default() };
+  match data_method
+  {
+    DataMethod::Regex => search_data_regex(tree, nodes, query_value, options, context)?,
+    DataMethod::Text | DataMethod::TextLiteral => search_data_line(tree, nodes, &data_method.line_pattern(query_value), options, context)?,
+  };
+  Ok(())
+}
+
+/**
+ *  Same as [query_data_callback] but send each matching node to `sender`, so results can be received incrementally from another thread.
+ *  The search continue even if the receiver was dropped.
+ */
+pub fn query_data_stream(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions, sender : Sender<TreeNodeId>) -> Result<()>
+{
+  let sender = Mutex::new(sender);
+  query_data_callback(tree, nodes, query_value, data_method, options, |node_id|
+  {
+    if let Ok(sender) = sender.lock()
+    {
+      let _ = sender.send(node_id);
+    }
+  })
+}
+
+/**
+ *  Search in `nodes` data using `data_method` and `options` like [query_data] and call `progress` with the number of nodes already searched.
+ *  The search run on another thread and `progress` is called periodically from the caller thread, so it doesn't need to be [Sync].
+ *  The total number of nodes to search is `nodes.len()`.
+ */
+pub fn query_data_progress<F : FnMut(usize)>(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions, progress : F) -> Result<Vec<TreeNodeId>>
+{
+  poll_progress(|processed|
+  {
+    let context = SearchContext{ processed : Some(processed), ..Default::default() };
+    match data_method
+    {
+      DataMethod::Regex => search_data_regex(tree, nodes, query_value, options, context),
+      DataMethod::Text | DataMethod::TextLiteral => search_data_line(tree, nodes, &data_method.line_pattern(query_value), options, context),
+    }
+  }, progress)
 }
 
-pub fn query_data(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod) -> Result<Vec<TreeNodeId>> 
+/**
+ *  Search in `nodes` data using `data_method` and `options` like [query_data] but return the result of the search for each node,
+ *  rather than skipping nodes which data can't be opened or read, the error is returned for that node.
+ *  Nodes without data are returned as not matching.
+ */
+pub fn query_data_checked(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<bool>)>>
 {
   match data_method
   {
-    DataMethod::Regex => query_data_regex(tree, nodes, query_value), 
-    DataMethod::Text =>  query_data_line(tree, nodes, query_value),
+    DataMethod::Regex => check_data_regex(tree, nodes, query_value, options, SearchContext::default()),
+    DataMethod::Text | DataMethod::TextLiteral => check_data_line(tree, nodes, &data_method.line_pattern(query_value), options, SearchContext::default()),
+  }
+}
+
+/**
+ *  Result of [query_data_summary].
+ */
+#[derive(Serialize)]
+pub struct DataSearchSummary
+{
+  /// Nodes which data matched.
+  pub matches : Vec<TreeNodeId>,
+  /// Total number of bytes read from nodes data during the search.
+  pub bytes_read : u64,
+  /// Number of nodes which data couldn't be searched because of an error.
+  pub errors : usize,
+}
+
+/// Search in `nodes` data using `data_method` like [query_data] and also return the number of bytes that were read and the number of nodes that errored.
+pub fn query_data_summary(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod) -> Result<DataSearchSummary>
+{
+  let bytes_read = AtomicU64::new(0);
+  let options = DataSearchOptions::default();
+  let context = SearchContext{ bytes_read : Some(&bytes_read), ..Default::default() };
+  let results = match data_method
+  {
+    DataMethod::Regex => check_data_regex(tree, nodes, query_value, &options, context)?,
+    DataMethod::Text | DataMethod::TextLiteral => check_data_line(tree, nodes, &data_method.line_pattern(query_value), &options, context)?,
+  };
+
+  let errors = results.iter().filter(|(_, result)| result.is_err()).count();
+  Ok(DataSearchSummary{ matches : matching_nodes(results), bytes_read : bytes_read.into_inner(), errors })
+}
+
+/// Keep only the nodes that matched, nodes that errored are discarded.
+fn matching_nodes(results : Vec<(TreeNodeId, Result<bool>)>) -> Vec<TreeNodeId>
+{
+  results.into_iter().filter_map(|(node_id, result)| matches!(result, Ok(true)).then(|| node_id)).collect()
+}
+
+/// Search in `nodes` data using `data_method` and `options` like [query_data], the search stop early when `cancel` is set to true.
+/// No partial results are returned : a [QueryCancelled] error is returned if the search was cancelled.
+pub fn query_data_cancellable(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions, cancel : &AtomicBool) -> Result<Vec<TreeNodeId>>
+{
+  let limit = ResultLimit::new(options.limit);
+  let context = SearchContext{ cancel : Some(cancel), limit : Some(&limit), ..Default::default() };
+  let results = match data_method
+  {
+    DataMethod::Regex => check_data_regex(tree, nodes, query_value, options, context)?,
+    DataMethod::Text | DataMethod::TextLiteral => check_data_line(tree, nodes, &data_method.line_pattern(query_value), options, context)?,
+  };
+
+  //a node whose read was interrupted has an error result, nodes not searched yet are just omitted
+  let interrupted = results.iter().any(|(_, result)| matches!(result, Err(err) if is_cancelled_error(err)));
+  match interrupted || cancel.load(atomic::Ordering::Relaxed)
+  {
+    true => Err(QueryCancelled.into()),
+    false =>
+    {
+      let mut matches = matching_nodes(results);
+      limit.truncate(&mut matches);
+      Ok(matches)
+    },
+  }
+}
+
+/// Return true if `err` is the error returned by a [DataReader] when the search was cancelled.
+fn is_cancelled_error(err : &anyhow::Error) -> bool
+{
+  err.downcast_ref::<std::io::Error>().and_then(|err| err.get_ref()).map_or(false, |err| err.is::<QueryCancelled>())
+}
+
+/// State shared by all the rayon workers during a data search.
+#[derive(Default, Clone, Copy)]
+struct SearchContext<'a>
+{
+  /// Add the number of bytes read.
+  bytes_read : Option<&'a AtomicU64>,
+  /// Stop the search when set to true.
+  cancel : Option<&'a AtomicBool>,
+  /// Add one each time a node was searched.
+  processed : Option<&'a AtomicUsize>,
+  /// Stop the search once enough nodes matched.
+  limit : Option<&'a ResultLimit>,
+  /// Called with each matching node as soon as it's found.
+  on_match : Option<&'a (dyn Fn(TreeNodeId) + Sync)>,
+}
+
+impl<'a> SearchContext<'a>
+{
+  fn is_cancelled(&self) -> bool
+  {
+    self.cancel.map_or(false, |cancel| cancel.load(atomic::Ordering::Relaxed))
+  }
+
+  /// Return true if the search was cancelled or if the limit of matching nodes was reached.
+  fn is_stopped(&self) -> bool
+  {
+    self.is_cancelled() || self.limit.map_or(false, ResultLimit::is_reached)
+  }
+
+  /// Must be called after a node was searched, with the result of the search.
+  fn node_searched(&self, node_id : TreeNodeId, result : &Result<bool>)
+  {
+    if let Ok(true) = result
+    {
+      if let Some(limit) = self.limit
+      {
+        limit.found();
+      }
+      if let Some(on_match) = self.on_match
+      {
+        on_match(node_id);
+      }
+    }
+    self.node_processed();
+  }
+
+  fn node_processed(&self)
+  {
+    if let Some(processed) = self.processed
+    {
+      processed.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+  }
+}
+
+/// Reader that update the [SearchContext] on each read and return a [QueryCancelled] error if the search was cancelled.
+/// The error kind is not [std::io::ErrorKind::Interrupted] as readers retry on this kind rather than returning the error.
+struct DataReader<'a, R>
+{
+  reader : R,
+  context : SearchContext<'a>,
+}
+
+impl<'a, R : Read> Read for DataReader<'a, R>
+{
+  fn read(&mut self, buf : &mut [u8]) -> std::io::Result<usize>
+  {
+    if self.context.is_cancelled()
+    {
+      return Err(std::io::Error::new(std::io::ErrorKind::Other, QueryCancelled))
+    }
+    let n = self.reader.read(buf)?;
+    if let Some(bytes_read) = self.context.bytes_read
+    {
+      bytes_read.fetch_add(n as u64, atomic::Ordering::Relaxed);
+    }
+    Ok(n)
   }
 }
 
 /// Search in `nodes` data if RegEx `query_value` match file content.
+/// The `data` attribute is searched, use [query_data] with [DataSearchOptions::attribute_name] to search another attribute.
 /// Use a `RegexBuilder` with unicode, dot_matches_new_line and case_insensitive set to true.
 /// Only Unicode 8 and ascii will match, 
 /// \x can be use to search for binary data.
 pub fn query_data_regex(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str) -> Result<Vec<TreeNodeId>>
+{
+  search_data_regex(tree, nodes, query_value, &DataSearchOptions::default(), SearchContext::default())
+}
+
+/// Same as [query_data_regex] but `query_value` must match at the start of the data, so it can be used to detect file types by their magic.
+pub fn query_data_regex_anchored(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str) -> Result<Vec<TreeNodeId>>
+{
+  let options = DataSearchOptions{ anchored : true, ..Default::default() };
+  search_data_regex(tree, nodes, query_value, &options, SearchContext::default())
+}
+
+/// Search in `nodes` data for the bytes sequence described by the hex string `hex` (`4D5A90`).
+/// Return an error before opening any file if `hex` is empty, has an odd length or contains non hex characters.
+pub fn query_data_hex(tree : &Tree, nodes : &Vec<TreeNodeId>, hex : &str) -> Result<Vec<TreeNodeId>>
+{
+  let bytes = parse_hex(hex)?;
+  if bytes.is_empty()
+  {
+    return Err(anyhow!("Empty hex pattern"))
+  }
+
+  //disable unicode so \x match a single byte
+  let pattern : String = "(?-u)".to_string() + &bytes.iter().map(|byte| format!("\\x{:02x}", byte)).collect::<String>();
+  let options = DataSearchOptions{ case_sensitive : Some(true), ..Default::default() };
+  search_data_regex(tree, nodes, &pattern, &options, SearchContext::default())
+}
+
+fn data_regex(query_value : &str, case_sensitive : bool, limits : &RegexLimits) -> Result<regex::bytes::Regex>
 {
   let mut builder = RegexBuilder::new(query_value);
   builder.unicode(true);//accept UTF-8 in regex exp,  
   builder.dot_matches_new_line(true);
-  builder.case_insensitive(true);
-  let query_compiled = builder.build()?;
+  builder.case_insensitive(!case_sensitive);
+  builder.size_limit(limits.size_limit);
+  builder.dfa_size_limit(limits.dfa_size_limit);
+  builder.build().map_err(regex_error)
+}
+
+fn data_line_regex(query_value : &str, case_sensitive : bool, limits : &RegexLimits) -> Result<RegexMatcher>
+{
+  let mut builder = RegexMatcherBuilder::new();
+  builder.case_insensitive(!case_sensitive);
+  builder.size_limit(limits.size_limit);
+  builder.dfa_size_limit(limits.dfa_size_limit);
+  Ok(builder.build(query_value)?)
+}
+
+fn search_data_regex(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions, context : SearchContext) -> Result<Vec<TreeNodeId>>
+{
+  let limit = ResultLimit::new(options.limit);
+  let context = SearchContext{ limit : Some(&limit), ..context };
+  let mut matches = matching_nodes(check_data_regex(tree, nodes, query_value, options, context)?);
+  limit.truncate(&mut matches);
+  Ok(matches)
+}
+
+fn check_data_regex(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions, context : SearchContext) -> Result<Vec<(TreeNodeId, Result<bool>)>>
+{
+  let query_compiled = options_data_regex(query_value, options)?;
 
   Ok(nodes.par_iter().filter_map(|node_id|
   {
-     if let Some(node) = tree.get_node_from_id(*node_id)
+     if context.is_stopped()
      {
-       if match_data_regex(&node, &query_compiled) 
-       {
-         return Some(*node_id)
-       }
+       return None
      }
-     None
+     let result = match tree.get_node_from_id(*node_id)
+     {
+       Some(node) => match_data_regex(&node, &query_compiled, options, context),
+       None => Err(anyhow!("Node not found")),
+     };
+     context.node_searched(*node_id, &result);
+     Some((*node_id, result))
   }).collect())
 }
 
-//return false on error so we continue on other nodes
-fn match_data_regex(node: &Node, query_compiled : &regex::bytes::Regex) -> bool
+/// Compile `query_value` to search it with [DataMethod::Regex] and `options`, 
+/// return an error if the regex is invalid or exceeds the limits, or if `options` can't be used to read the data.
+fn options_data_regex(query_value : &str, options : &DataSearchOptions) -> Result<regex::bytes::Regex>
+{
+  //nothing could be read and every node would fail with an unexpected end of data
+  if options.buffer_size == 0
+  {
+    return Err(anyhow!("Buffer size must be greater than 0"))
+  }
+  data_regex(&options.regex_pattern(query_value), options.is_case_sensitive(query_value, false), &options.regex_limits)
+}
+
+/// Open the `options.attribute_name` data of `node` to search it, reads update `context`. 
+/// Return the reader and the size of the data, `None` if the node has no data or if it's bigger than `options.max_file_size`, 
+/// or an error if the data can't be opened so the caller can decide to continue on other nodes.
+fn open_data<'a>(node : &Node, options : &DataSearchOptions, context : SearchContext<'a>) -> Result<Option<(DataReader<'a, Box<dyn VFile>>, u64)>>
 {
-  let data = match node.value().get_value("data")
+  let data = match node.value().get_value(&options.attribute_name)
   {
-    None => return false,
+    None => return Ok(None),
     Some(data) => data,
   };
   let builder = match data.try_as_vfile_builder()
   {
-    None => return false,
+    None => return Ok(None),
     Some(builder) => builder,
   };
+  let size = builder.size();
+  if options.max_file_size.map_or(false, |max_file_size| size > max_file_size)
+  {
+    return Ok(None)
+  }
+  Ok(Some((DataReader{ reader : builder.open()?, context }, size)))
+}
+
+/// Read the start of `file` until `buff` is full or the `size` bytes of the file were read and return the number of bytes read.
+/// A read can return less than asked, so a magic at the start of the data could otherwise span two reads.
+fn fill_buffer<R : Read>(file : &mut R, buff : &mut [u8], size : u64) -> Result<usize>
+{
+  let mut len = 0;
+  while len < buff.len() && (len as u64) < size
+  {
+    match file.read(&mut buff[len..])?
+    {
+      0 => break,
+      n => len += n,
+    }
+  }
+  Ok(len)
+}
+
+/// Number of bytes of the previous read kept when searching data with a regex, 
+/// a match longer than this size that span two reads can still be missed.
+const REGEX_OVERLAP : usize = 4096;
 
-  let mut file = match builder.open()
+//return an error if the data can't be opened or read, so the caller can decide to continue on other nodes
+fn match_data_regex(node: &Node, query_compiled : &regex::bytes::Regex, options : &DataSearchOptions, context : SearchContext) -> Result<bool>
+{
+  let (mut file, file_size) = match open_data(node, options, context)?
   {
-    Err(_)=> return false,
-    Ok(file) => file,
+    None => return Ok(false),
+    Some(data) => data,
   };
 
-  let mut buff = [0; 4096];
+  //the end of the previous read is kept at the start of the buffer so a match spanning two reads is found
+  let mut buff = vec![0; REGEX_OVERLAP + options.buffer_size];
+  let mut kept = 0;
   let mut readed = 0;
-  let file_size = builder.size();
+
+  if options.anchored
+  {
+    let window_len = fill_buffer(&mut file, &mut buff, file_size)?;
+    return Ok(query_compiled.is_match(&buff[..window_len]))
+  }
 
   while readed < file_size
   {
-    match file.read(&mut buff)
+    let n = match file.read(&mut buff[kept..])?
     {
-      Ok(n) => { readed += n as u64; if (n <= 0) {return false} }, 
-      Err(_err) => return false,
+      0 => return Err(anyhow!("Unexpected end of data")),
+      n => n,
     };
+    readed += n as u64;
 
-    let res = query_compiled.is_match(&buff);
-    if res == true 
+    let window_len = kept + n;
+    if query_compiled.is_match(&buff[..window_len])
     {
-      return true
+      return Ok(true)
     }
+
+    kept = REGEX_OVERLAP.min(window_len);
+    buff.copy_within(window_len - kept..window_len, 0);
   }
 
-  false  
+  Ok(false)
 }
 
 /**
- *  Search for all `nodes` if RegEx `query_value` match file content.
- *  Search line of text, line by line (search for a '\n' then match on a line),
- *  Line size is limited by heap_limit (1024*1024*100).
- *  It takes a str (utf8) string as argument and search for both utf-8 and utf-16.
- **/
-pub fn query_data_line(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str) -> Result<Vec<TreeNodeId>>
+ *  Search all `patterns` in `nodes` data reading each file only once and return for each matching node the index of the patterns that matched.
+ *  The search of a node stop as soon as all the patterns were found. `options` apply to each pattern like with [query_data], 
+ *  the case sensitivity is decided for each pattern so smart case only match a pattern with an uppercase letter case sensitively.
+ *  Like [query_data_checked] a node which data can't be opened or read is returned with the error, nodes without match are omitted.
+ *  It's the way to run several searches on the same nodes, calling [query_data] for each pattern would open and read each file again.
+ */
+pub fn query_data_multi(tree : &Tree, nodes : &Vec<TreeNodeId>, patterns : &[&str], data_method : DataMethod, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<Vec<usize>>)>>
 {
-  let query_compiled = RegexMatcher::new(query_value)?;
+  //nothing could be read and every node would fail with an unexpected end of data
+  if options.buffer_size == 0
+  {
+    return Err(anyhow!("Buffer size must be greater than 0"))
+  }
 
-  Ok(nodes.par_iter().filter_map(|node_id|
+  let found = match data_method
   {
-     if let Some(node) = tree.get_node_from_id(*node_id)
-     {
-       if match_data_line(&node, &query_compiled) 
-       {
-         return Some(*node_id)
-       }
-     }
-     None
+    DataMethod::Regex =>
+    {
+      let patterns : Vec<String> = patterns.iter().map(|pattern| case_pattern(&options.regex_pattern(pattern), options.is_case_sensitive(pattern, false))).collect();
+      let mut builder = RegexSetBuilder::new(&patterns);
+      builder.unicode(true);
+      builder.dot_matches_new_line(true);
+      builder.size_limit(options.regex_limits.size_limit);
+      builder.dfa_size_limit(options.regex_limits.dfa_size_limit);
+      let set = builder.build().map_err(regex_error)?;
+      nodes.par_iter().map(|node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, match_data_regex_set(&node, &set, options)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+    DataMethod::Text | DataMethod::TextLiteral =>
+    {
+      let patterns : Vec<String> = patterns.iter().map(|pattern|
+      {
+        let pattern = data_method.line_pattern(pattern);
+        case_pattern(&options.pattern(&pattern, true), options.is_case_sensitive(&pattern, true))
+      }).collect();
+      let set = RegexSetBuilder::new(&patterns).size_limit(options.regex_limits.size_limit)
+                                               .dfa_size_limit(options.regex_limits.dfa_size_limit)
+                                               .build().map_err(regex_error)?;
+      //match lines containing any of the patterns then check which ones matched, each pattern carry its own case flag
+      let query_compiled = data_line_regex(&patterns.join("|"), true, &options.regex_limits)?;
+      nodes.par_iter().map_init(|| line_searcher(options), |searcher, node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, match_data_line_set(&node, &query_compiled, &set, searcher, options)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+  };
+
+  Ok(found.into_iter().filter_map(|(node_id, found)|
+  {
+    let indexes = found.map(|found| found.iter().enumerate().filter(|(_, found)| **found).map(|(index, _)| index).collect::<Vec<usize>>());
+    match indexes
+    {
+      Ok(indexes) if indexes.is_empty() => None,
+      indexes => Some((node_id, indexes)),
+    }
   }).collect())
 }
 
-fn match_data_line(node: &Node, query_compiled : &RegexMatcher) -> bool
+/// Wrap `pattern` in a group setting its case sensitivity, so patterns compiled together keep their own.
+fn case_pattern(pattern : &str, case_sensitive : bool) -> String
+{
+  match case_sensitive
+  {
+    true => format!("(?-i:{})", pattern),
+    false => format!("(?i:{})", pattern),
+  }
+}
+
+fn match_data_regex_set(node : &Node, set : &RegexSet, options : &DataSearchOptions) -> Result<Vec<bool>>
 {
-  let data = match node.value().get_value("data")
+  let mut found = vec![false; set.len()];
+  let (mut file, file_size) = match open_data(node, options, SearchContext::default())?
   {
-    None => return false,
+    None => return Ok(found),
     Some(data) => data,
   };
-  let builder = match data.try_as_vfile_builder()
+
+  let mut buff = vec![0; REGEX_OVERLAP + options.buffer_size];
+  let mut kept = 0;
+  let mut readed = 0;
+
+  if options.anchored
   {
-    None => return false,
-    Some(builder) => builder,
-  };
+    let window_len = fill_buffer(&mut file, &mut buff, file_size)?;
+    for index in set.matches(&buff[..window_len]).into_iter()
+    {
+      found[index] = true;
+    }
+    return Ok(found)
+  }
 
-  let file = match builder.open()
+  while readed < file_size
   {
-    Err(_)=> return false,
-    Ok(file) => file,
-  };
+    let n = match file.read(&mut buff[kept..])?
+    {
+      0 => return Err(anyhow!("Unexpected end of data")),
+      n => n,
+    };
+    readed += n as u64;
 
-  //optimize by having one builder , it's slow ...
-  let mut searcher_builder = SearcherBuilder::new();
-  searcher_builder.heap_limit(Some(1024*1024*100));//will allocate 100M each time 
-  let mut searcher = searcher_builder.build(); //reuse it, or create it in query_data_line if possible ?
-  let mut matches: Vec<u64> = vec![];
+    let window_len = kept + n;
+    for index in set.matches(&buff[..window_len]).into_iter()
+    {
+      found[index] = true;
+    }
+    if found.iter().all(|found| *found)
+    {
+      break
+    }
 
-  //could use a UTF8 here too
-  let sink = Bytes(|lnum, line| {
-    let _match = match query_compiled.find(line) 
+    kept = REGEX_OVERLAP.min(window_len);
+    buff.copy_within(window_len - kept..window_len, 0);
+  }
+  Ok(found)
+}
+
+fn match_data_line_set(node : &Node, query_compiled : &RegexMatcher, set : &RegexSet, searcher : &mut Searcher, options : &DataSearchOptions) -> Result<Vec<bool>>
+{
+  let mut found = vec![false; set.len()];
+  let (file, _) = match open_data(node, options, SearchContext::default())?
+  {
+    None => return Ok(found),
+    Some(data) => data,
+  };
+
+  let sink = Bytes(|_lnum, line| {
+    for index in set.matches(line).into_iter()
     {
-        Err(_err) => {}, //{ println!("Error {}", err); }, 
-        Ok(_) => matches.push(lnum), 
-    };
-    Ok(true)
+      found[index] = true;
+    }
+    Ok(!found.iter().all(|found| *found)) //stop when all patterns were found
   });
+  searcher.search_reader(query_compiled, file, sink)?;
+  Ok(found)
+}
 
-  let _ = searcher.search_reader(&query_compiled, file, sink); //return result and error so we can have more info ? 
-  if matches.len() > 0
+/**
+ *  Search in `nodes` data using `data_method` and `options` and return for each matching node the offsets in bytes of the start of each match.
+ *  For [DataMethod::Text] the offset of the first match of each matching line is returned, 
+ *  for [DataMethod::Regex] with [DataSearchOptions::anchored] only a match at the start of the data can be returned.
+ *  Like [query_data_checked] a node which data can't be opened or read is returned with the error, nodes without match are omitted.
+ */
+pub fn query_data_offsets(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<Vec<u64>>)>>
+{
+  let offsets = match data_method
   {
-    return true;
-  }
+    DataMethod::Regex => 
+    {
+      let query_compiled = options_data_regex(query_value, options)?;
+      nodes.par_iter().map(|node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, data_regex_offsets(&node, &query_compiled, options)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+    DataMethod::Text | DataMethod::TextLiteral =>
+    {
+      let query_compiled = options_data_line_regex(&data_method.line_pattern(query_value), options)?;
+      //Searcher is not Sync, we create one by rayon worker and reuse it for each node
+      nodes.par_iter().map_init(|| line_searcher(options), |searcher, node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, data_line_offsets(&node, &query_compiled, searcher, options)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+  };
 
-  false
+  Ok(offsets.into_iter().filter(|(_, offsets)| !matches!(offsets, Ok(offsets) if offsets.is_empty())).collect())
+}
+
+/**
+ *  Search in `nodes` data using `data_method` and `options` and return for each matching node the number of matches.
+ *  For [DataMethod::Regex] a match found again when the end of a read is searched with the next one is counted once, 
+ *  for [DataMethod::Text] the number of matching lines is returned.
+ *  Like [query_data_checked] a node which data can't be opened or read is returned with the error, nodes without match are omitted.
+ */
+pub fn query_data_count(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, data_method : DataMethod, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<u64>)>>
+{
+  let counts = match data_method
+  {
+    DataMethod::Regex =>
+    {
+      let query_compiled = options_data_regex(query_value, options)?;
+      nodes.par_iter().map(|node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, data_regex_offsets(&node, &query_compiled, options).map(|offsets| offsets.len() as u64)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+    DataMethod::Text | DataMethod::TextLiteral =>
+    {
+      let query_compiled = options_data_line_regex(&data_method.line_pattern(query_value), options)?;
+      nodes.par_iter().map_init(|| line_searcher(options), |searcher, node_id| match tree.get_node_from_id(*node_id)
+      {
+        Some(node) => (*node_id, data_line_count(&node, &query_compiled, searcher, options)),
+        None => (*node_id, Err(anyhow!("Node not found"))),
+      }).collect::<Vec<_>>()
+    },
+  };
+
+  Ok(counts.into_iter().filter(|(_, count)| !matches!(count, Ok(0))).collect())
+}
+
+fn data_line_count(node : &Node, query_compiled : &RegexMatcher, searcher : &mut Searcher, options : &DataSearchOptions) -> Result<u64>
+{
+  let (file, _) = match open_data(node, options, SearchContext::default())?
+  {
+    None => return Ok(0),
+    Some(data) => data,
+  };
+
+  let mut count = 0;
+  let sink = Bytes(|_lnum, _line| {
+    count += 1;
+    Ok(true)
+  });
+  searcher.search_reader(query_compiled, file, sink)?;
+  Ok(count)
+}
+
+fn data_regex_offsets(node : &Node, query_compiled : &regex::bytes::Regex, options : &DataSearchOptions) -> Result<Vec<u64>>
+{
+  let (mut file, size) = match open_data(node, options, SearchContext::default())?
+  {
+    None => return Ok(Vec::new()),
+    Some(data) => data,
+  };
+
+  match options.anchored
+  {
+    //the regex only match at the start of the searched buffer, so only the start of the data is searched
+    true =>
+    {
+      let mut buff = vec![0; REGEX_OVERLAP + options.buffer_size];
+      let window_len = fill_buffer(&mut file, &mut buff, size)?;
+      Ok(query_compiled.find(&buff[..window_len]).map(|found| vec![found.start() as u64]).unwrap_or_default())
+    },
+    false => reader_regex_offsets(file, size, query_compiled, options.buffer_size),
+  }
+}
+
+/// Return the offset of the start of each match of `query_compiled` in the first `size` bytes of `reader`, read `buffer_size` bytes at a time.
+/// Return an error if `reader` can't be read or ends before `size` bytes.
+fn reader_regex_offsets<R : Read>(mut reader : R, size : u64, query_compiled : &regex::bytes::Regex, buffer_size : usize) -> Result<Vec<u64>>
+{
+  let mut offsets = Vec::new();
+  let mut buff = vec![0; REGEX_OVERLAP + buffer_size];
+  let mut kept = 0;
+  let mut readed = 0;
+  //end of the last reported match in the file
+  let mut covered = 0;
+
+  while readed < size
+  {
+    let n = match reader.read(&mut buff[kept..])?
+    {
+      0 => return Err(anyhow!("Unexpected end of data")),
+      n => n,
+    };
+    readed += n as u64;
+
+    //offset of the start of the buffer in the file
+    let window_len = kept + n;
+    let window_offset = readed - window_len as u64;
+    for found in query_compiled.find_iter(&buff[..window_len])
+    {
+      //the kept part of the buffer is searched again, a match starting inside the last reported one 
+      //is the same match found again or a shorter part of it (\w+ in the middle of a word)
+      let start = window_offset + found.start() as u64;
+      let end = window_offset + found.end() as u64;
+      if start < covered || offsets.last().map_or(false, |last| start <= *last)
+      {
+        covered = covered.max(end);
+        continue
+      }
+      offsets.push(start);
+      covered = end;
+    }
+
+    kept = REGEX_OVERLAP.min(window_len);
+    buff.copy_within(window_len - kept..window_len, 0);
+  }
+
+  Ok(offsets)
+}
+
+/// Sink that collect the offset of the first match of each matching line.
+struct OffsetSink<'a>
+{
+  matcher : &'a RegexMatcher,
+  offsets : Vec<u64>,
+}
+
+impl<'a> Sink for OffsetSink<'a>
+{
+  type Error = std::io::Error;
+
+  fn matched(&mut self, _searcher : &Searcher, line : &SinkMatch<'_>) -> std::result::Result<bool, std::io::Error>
+  {
+    let start = match self.matcher.find(line.bytes())
+    {
+      Ok(Some(found)) => found.start() as u64,
+      _ => 0,
+    };
+    self.offsets.push(line.absolute_byte_offset() + start);
+    Ok(true)
+  }
+}
+
+fn data_line_offsets(node : &Node, query_compiled : &RegexMatcher, searcher : &mut Searcher, options : &DataSearchOptions) -> Result<Vec<u64>>
+{
+  let (file, _) = match open_data(node, options, SearchContext::default())?
+  {
+    None => return Ok(Vec::new()),
+    Some(data) => data,
+  };
+
+  let mut sink = OffsetSink{ matcher : query_compiled, offsets : Vec::new() };
+  searcher.search_reader(query_compiled, file, &mut sink)?;
+  Ok(sink.offsets)
+}
+
+/**
+ *  Search for all `nodes` if RegEx `query_value` match file content.
+ *  Search line of text, line by line (search for a '\n' then match on a line),
+ *  Line size is limited by heap_limit (1024*1024*100).
+ *  It takes a str (utf8) string as argument and search utf-8 and utf-16 with a BOM,
+ *  use [query_data] with [DataSearchOptions::encoding] to search utf-16 without BOM.
+ *  The `data` attribute is searched, use [query_data] with [DataSearchOptions::attribute_name] to search another attribute.
+ **/
+pub fn query_data_line(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str) -> Result<Vec<TreeNodeId>>
+{
+  search_data_line(tree, nodes, query_value, &DataSearchOptions::default(), SearchContext::default())
+}
+
+fn search_data_line(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions, context : SearchContext) -> Result<Vec<TreeNodeId>>
+{
+  let limit = ResultLimit::new(options.limit);
+  let context = SearchContext{ limit : Some(&limit), ..context };
+  let mut matches = matching_nodes(check_data_line(tree, nodes, query_value, options, context)?);
+  limit.truncate(&mut matches);
+  Ok(matches)
+}
+
+fn check_data_line(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions, context : SearchContext) -> Result<Vec<(TreeNodeId, Result<bool>)>>
+{
+  let query_compiled = options_data_line_regex(query_value, options)?;
+
+  //Searcher is not Sync, we create one by rayon worker and reuse it for each node
+  Ok(nodes.par_iter().map_init(|| line_searcher(options), |searcher, node_id|
+  {
+     if context.is_stopped()
+     {
+       return None
+     }
+     let result = match tree.get_node_from_id(*node_id)
+     {
+       Some(node) => match_data_line(&node, &query_compiled, searcher, options, context),
+       None => Err(anyhow!("Node not found")),
+     };
+     context.node_searched(*node_id, &result);
+     Some((*node_id, result))
+  }).filter_map(|result| result).collect())
+}
+
+/**
+ *  Search `nodes` data line by line using `options` like [query_data_line] and return for each matching node the line number and the content of each matching line.
+ *  Lines are decoded lossily to UTF-8 and their line terminator is removed, line size is still limited by the heap limit.
+ *  Like [query_data_checked] a node which data can't be opened or read is returned with the error, nodes without match are omitted.
+ */
+pub fn query_data_line_context(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<Vec<(u64, String)>>)>>
+{
+  Ok(query_data_line_matches(tree, nodes, query_value, options)?.into_iter().map(|(node_id, lines)|
+  {
+    (node_id, lines.map(|lines| lines.into_iter().map(|line| (line.line_number, line.line)).collect()))
+  }).collect())
+}
+
+/**
+ *  A line matched by [query_data_line_matches].
+ */
+#[derive(Serialize)]
+pub struct DataLineMatch
+{
+  /// Number of the line, starting at 1.
+  pub line_number : u64,
+  /// Byte range of each match in the line, ranges are offsets in the line bytes before they're decoded to `line`.
+  pub matches : Vec<Range<usize>>,
+  /// Content of the line decoded lossily to UTF-8 without its line terminator.
+  pub line : String,
+}
+
+/**
+ *  Same as [query_data_line_context] but also return the byte range of each match in the matching lines, to highlight them.
+ *  Like [query_data_checked] a node which data can't be opened or read is returned with the error, nodes without match are omitted.
+ */
+pub fn query_data_line_matches(tree : &Tree, nodes : &Vec<TreeNodeId>, query_value : &str, options : &DataSearchOptions) -> Result<Vec<(TreeNodeId, Result<Vec<DataLineMatch>>)>>
+{
+  let query_compiled = options_data_line_regex(query_value, options)?;
+
+  Ok(nodes.par_iter().map_init(|| line_searcher(options), |searcher, node_id|
+  {
+    let lines = match tree.get_node_from_id(*node_id)
+    {
+      Some(node) => data_line_context(&node, &query_compiled, searcher, options),
+      None => Err(anyhow!("Node not found")),
+    };
+    match &lines
+    {
+      Ok(found) if found.is_empty() => None,
+      _ => Some((*node_id, lines)),
+    }
+  }).filter_map(|lines| lines).collect())
+}
+
+fn data_line_context(node : &Node, query_compiled : &RegexMatcher, searcher : &mut Searcher, options : &DataSearchOptions) -> Result<Vec<DataLineMatch>>
+{
+  let mut lines = Vec::new();
+  let (file, _) = match open_data(node, options, SearchContext::default())?
+  {
+    None => return Ok(lines),
+    Some(data) => data,
+  };
+
+  let sink = Bytes(|lnum, line| {
+    let mut matches = Vec::new();
+    let _ = query_compiled.find_iter(line, |found| 
+    {
+      matches.push(found.start()..found.end());
+      true
+    });
+    lines.push(DataLineMatch{ line_number : lnum, matches, line : String::from_utf8_lossy(line).trim_end_matches(&['\r', '\n'][..]).to_string() });
+    Ok(true)
+  });
+  searcher.search_reader(query_compiled, file, sink)?;
+  Ok(lines)
+}
+
+/// Compile `query_value` to search it line by line with `options`, return an error if the regex is invalid or exceeds the limits.
+fn options_data_line_regex(query_value : &str, options : &DataSearchOptions) -> Result<RegexMatcher>
+{
+  data_line_regex(&options.pattern(query_value, true), options.is_case_sensitive(query_value, true), &options.regex_limits)
+}
+
+fn line_searcher(options : &DataSearchOptions) -> Searcher
+{
+  let mut searcher_builder = SearcherBuilder::new();
+  searcher_builder.heap_limit(Some(options.heap_limit));//will allocate up to heap_limit for each searcher
+  //BOM sniffing stay enabled so a file with a BOM is always decoded with the right encoding and never searched twice
+  let encoding = match options.encoding
+  {
+    TextEncoding::Auto => None,
+    TextEncoding::Utf16Le => Encoding::new("utf-16le").ok(),
+    TextEncoding::Utf16Be => Encoding::new("utf-16be").ok(),
+  };
+  searcher_builder.encoding(encoding);
+  searcher_builder.build()
+}
+
+fn match_data_line(node: &Node, query_compiled : &RegexMatcher, searcher : &mut Searcher, options : &DataSearchOptions, context : SearchContext) -> Result<bool>
+{
+  let (file, _) = match open_data(node, options, context)?
+  {
+    None => return Ok(false),
+    Some(data) => data,
+  };
+
+  let mut matches: Vec<u64> = vec![];
+
+  //could use a UTF8 here too
+  let sink = Bytes(|lnum, line| {
+    query_compiled.find(line).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+    matches.push(lnum);
+    Ok(true)
+  });
+
+  searcher.search_reader(&query_compiled, file, sink)?;
+  Ok(!matches.is_empty())
+}
+
+/// Resolved [Node] information used to display a query result.
+#[derive(Clone, Serialize)]
+pub struct NodeRecord
+{
+  pub id : TreeNodeId,
+  pub name : String,
+  pub path : String,
+  /// Size of the node `data` attribute if it has one.
+  pub size : Option<u64>,
+}
+
+impl NodeRecord
+{
+  /// Resolve node `id` from `tree`, return `None` if the node can't be found.
+  pub fn new(tree : &Tree, id : TreeNodeId) -> Option<NodeRecord>
+  {
+    let node = tree.get_node_from_id(id)?;
+    let path = tree.node_path(id).unwrap_or_default();
+    Some(NodeRecord{ id, name : node.name(), path, size : data_size(&node) })
+  }
+}
+
+fn data_size(node : &Node) -> Option<u64>
+{
+  let data = node.value().get_value("data")?;
+  let builder = data.try_as_vfile_builder()?;
+  Some(builder.size())
+}
+
+/// Return `nodes` which `data` attribute is an empty (zero byte) file.
+/// Only the file size is checked, no data is read.
+pub fn empty_files(tree : &Tree, nodes : &Vec<TreeNodeId>) -> Vec<TreeNodeId>
+{
+  nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    match data_size(&node)
+    {
+      Some(0) => Some(*node_id),
+      _ => None,
+    }
+  }).collect()
+}
+
+/// Return `nodes` that have any [ValueTypeId::VFileBuilder] attribute which is an empty (zero byte) file, nested attributes are searched too.
+pub fn empty_vfiles(tree : &Tree, nodes : &Vec<TreeNodeId>) -> Vec<TreeNodeId>
+{
+  let is_empty = |attribute : &Attribute| attribute.value().try_as_vfile_builder().map_or(false, |builder| builder.size() == 0);
+  match_type_nodes_where(tree, nodes, &ValueTypeId::VFileBuilder, true, &is_empty)
+}
+
+/// [NodeRecord] with the first-level scalar attributes of the node, serialized by [results_to_json].
+#[derive(Serialize)]
+struct NodeRecordAttributes
+{
+  #[serde(flatten)]
+  record : NodeRecord,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  attributes : Option<BTreeMap<String, String>>,
+}
+
+/// Return the first-level attributes of `node` which value is not a container or a file, converted to string.
+fn scalar_attributes(node : &Node) -> BTreeMap<String, String>
+{
+  node.value().attributes().iter().filter(|attribute|
+  {
+    let type_id = attribute.type_id();
+    type_id != ValueTypeId::Attributes && type_id != ValueTypeId::ReflectStruct && type_id != ValueTypeId::VFileBuilder
+  }).map(|attribute| (attribute.name().to_string(), attribute.value().to_string())).collect()
+}
+
+/**
+ *  Serialize `nodes` to a JSON array of objects with the `id`, `name`, `path` and `size` of each node, ids that can't be resolved are ignored.
+ *  If `with_attributes` is true an `attributes` object containing the first-level scalar attributes of the node is added,
+ *  nested attributes are not included as a full dump can be large.
+ */
+pub fn results_to_json(tree : &Tree, nodes : &[TreeNodeId], with_attributes : bool) -> Result<String>
+{
+  let records : Vec<NodeRecordAttributes> = nodes.par_iter().filter_map(|id|
+  {
+    let record = NodeRecord::new(tree, *id)?;
+    let attributes = match with_attributes
+    {
+      true => Some(scalar_attributes(&tree.get_node_from_id(*id)?)),
+      false => None,
+    };
+    Some(NodeRecordAttributes{ record, attributes })
+  }).collect();
+
+  Ok(serde_json::to_string(&records)?)
+}
+
+/**
+ *  Self describing report of a query : the query, its scope, when it was run, by which version of this crate and the matching [NodeRecord].
+ */
+#[derive(Serialize)]
+pub struct ReportBundle
+{
+  pub query : String,
+  /// Path from where the query was run, `None` for the whole tree.
+  pub scope : Option<String>,
+  pub executed_at : DateTime<Utc>,
+  pub version : String,
+  pub records : Vec<NodeRecord>,
+}
+
+impl ReportBundle
+{
+  /// Create a report for `query` resolving matching `ids` to [NodeRecord], ids that can't be resolved are ignored.
+  pub fn build(tree : &Tree, query : &str, ids : &Vec<TreeNodeId>) -> ReportBundle
+  {
+    let records = ids.par_iter().filter_map(|id| NodeRecord::new(tree, *id)).collect();
+    ReportBundle{ query : query.to_string(), scope : None, executed_at : Utc::now(), version : env!("CARGO_PKG_VERSION").to_string(), records }
+  }
+
+  /// Set the path from where the query was run.
+  pub fn with_scope(mut self, scope : &str) -> ReportBundle
+  {
+    self.scope = Some(scope.to_string());
+    self
+  }
+
+  /// Serialize the report to JSON.
+  pub fn to_json(&self) -> Result<String>
+  {
+    Ok(serde_json::to_string(self)?)
+  }
+}
+
+/**
+ *  Key used to sort [NodeRecord] by [results_page].
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum SortKey
+{
+  /// Sort by node name.
+  Name,
+  /// Sort by node full path.
+  Path,
+  /// Sort by node data size, nodes without data come first.
+  Size,
+}
+
+/**
+ *  Resolve all `ids` to [NodeRecord], sort them by `sort_by` then by id
+ *  and return only the window starting at `offset` containing at most `limit` records.
+ *  Ids that can't be resolved are ignored.
+ */
+pub fn results_page(tree : &Tree, ids : &Vec<TreeNodeId>, sort_by : SortKey, ascending : bool, offset : usize, limit : usize) -> Vec<NodeRecord>
+{
+  let mut records : Vec<NodeRecord> = ids.par_iter().filter_map(|id| NodeRecord::new(tree, *id)).collect();
+
+  //ties are always ordered by ascending id so pages are stable whatever the order
+  records.par_sort_unstable_by(|a, b|
+  {
+    let order = match sort_by
+    {
+      SortKey::Name => a.name.cmp(&b.name),
+      SortKey::Path => a.path.cmp(&b.path),
+      SortKey::Size => a.size.cmp(&b.size),
+    };
+    let order = match ascending
+    {
+      true => order,
+      false => order.reverse(),
+    };
+    order.then_with(|| a.id.cmp(&b.id))
+  });
+
+  records.into_iter().skip(offset).take(limit).collect()
+}
+
+/**
+ *  Sort `records` by name for display : comparison is case-insensitive, ignore accents of latin letters
+ *  and compare digits numerically so `file2` come before `file10`.
+ *  This is unrelated to the [TreeNodeId] sort used by [Op](crate::filter::Op).
+ */
+pub fn sort_names_natural(records : &mut [NodeRecord])
+{
+  records.sort_by(|a, b| natural_cmp(&a.name, &b.name).then_with(|| a.name.cmp(&b.name)));
+}
+
+fn natural_cmp(a : &str, b : &str) -> Ordering
+{
+  let a = fold_name(a);
+  let b = fold_name(b);
+  let (mut i, mut j) = (0, 0);
+
+  while i < a.len() && j < b.len()
+  {
+    if a[i].is_ascii_digit() && b[j].is_ascii_digit()
+    {
+      let start_a = i;
+      while i < a.len() && a[i].is_ascii_digit() { i += 1; }
+      let start_b = j;
+      while j < b.len() && b[j].is_ascii_digit() { j += 1; }
+
+      let number_a = trim_zeros(&a[start_a..i]);
+      let number_b = trim_zeros(&b[start_b..j]);
+      let ordering = number_a.len().cmp(&number_b.len()).then_with(|| number_a.cmp(number_b));
+      if ordering != Ordering::Equal
+      {
+        return ordering
+      }
+    }
+    else
+    {
+      let ordering = a[i].cmp(&b[j]);
+      if ordering != Ordering::Equal
+      {
+        return ordering
+      }
+      i += 1;
+      j += 1;
+    }
+  }
+  (a.len() - i).cmp(&(b.len() - j))
+}
+
+fn trim_zeros(digits : &[char]) -> &[char]
+{
+  let start = digits.iter().position(|c| *c != '0').unwrap_or(digits.len());
+  &digits[start..]
+}
+
+fn fold_name(name : &str) -> Vec<char>
+{
+  name.chars().flat_map(|c| c.to_lowercase()).map(fold_accent).collect()
+}
+
+//only fold lowercase latin letters, other characters are kept as is
+fn fold_accent(c : char) -> char
+{
+  match c
+  {
+    'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+    'ç' => 'c',
+    'è' | 'é' | 'ê' | 'ë' => 'e',
+    'ì' | 'í' | 'î' | 'ï' => 'i',
+    'ñ' => 'n',
+    'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+    'ù' | 'ú' | 'û' | 'ü' => 'u',
+    'ý' | 'ÿ' => 'y',
+    _ => c,
+  }
+}
+
+/**
+ *  Boolean condition over the named patterns of a [Rule].
+ */
+pub enum Condition
+{
+  /// Pattern with this name was found.
+  Pattern(String),
+  /// Any pattern of the rule was found.
+  Any,
+  /// All patterns of the rule were found.
+  All,
+  And(Box<Condition>, Box<Condition>),
+  Or(Box<Condition>, Box<Condition>),
+  Not(Box<Condition>),
+}
+
+impl Condition
+{
+  fn eval(&self, rule : &Rule, found : &[bool]) -> bool
+  {
+    match self
+    {
+      Condition::Pattern(name) => rule.patterns.iter().zip(found).any(|((pattern_name, _), found)| pattern_name == name && *found),
+      Condition::Any => found.iter().any(|found| *found),
+      Condition::All => found.iter().all(|found| *found),
+      Condition::And(left, right) => left.eval(rule, found) && right.eval(rule, found),
+      Condition::Or(left, right) => left.eval(rule, found) || right.eval(rule, found),
+      Condition::Not(condition) => !condition.eval(rule, found),
+    }
+  }
+}
+
+/**
+ *  A named set of string or hex patterns and a [Condition] over them used by [match_rules].
+ */
+pub struct Rule
+{
+  pub name : String,
+  pub patterns : Vec<(String, Vec<u8>)>,
+  pub condition : Condition,
+}
+
+impl Rule
+{
+  /// Create a new rule without patterns.
+  pub fn new(name : &str, condition : Condition) -> Rule
+  {
+    Rule{ name : name.to_string(), patterns : Vec::new(), condition }
+  }
+
+  /// Add a text pattern `name` matching the UTF-8 bytes of `text`.
+  pub fn text(mut self, name : &str, text : &str) -> Rule
+  {
+    self.patterns.push((name.to_string(), text.as_bytes().to_vec()));
+    self
+  }
+
+  /// Add a binary pattern `name` from an hexadecimal string like `4D5A90`.
+  pub fn hex(mut self, name : &str, hex : &str) -> Result<Rule>
+  {
+    self.patterns.push((name.to_string(), parse_hex(hex)?));
+    Ok(self)
+  }
+}
+
+/// Convert an hexadecimal string to bytes, return an error if its length is odd or if it contains non hexadecimal characters.
+pub fn parse_hex(hex : &str) -> Result<Vec<u8>>
+{
+  if hex.len() % 2 != 0
+  {
+    return Err(anyhow!("Invalid hex pattern {} : odd length", hex))
+  }
+  if let Some(c) = hex.chars().find(|c| !c.is_ascii_hexdigit())
+  {
+    return Err(anyhow!("Invalid hex pattern {} : invalid character {:?}", hex, c))
+  }
+  Ok((0..hex.len()).step_by(2).map(|index| u8::from_str_radix(&hex[index..index+2], 16).unwrap()).collect())
+}
+
+/**
+ *  Evaluate all `rules` on `nodes` data and return for each node the names of the rules that matched.
+ *  The literals of every rules are searched with a single Aho-Corasick automaton during one read of each file.
+ *  Nodes without any matching rule are omitted.
+ */
+pub fn match_rules(tree : &Tree, nodes : &Vec<TreeNodeId>, rules : &[Rule]) -> Vec<(TreeNodeId, Vec<String>)>
+{
+  let literals : Vec<&Vec<u8>> = rules.iter().flat_map(|rule| rule.patterns.iter().map(|(_, pattern)| pattern)).collect();
+  let automaton = AhoCorasick::new(&literals);
+  let max_len = literals.iter().map(|literal| literal.len()).max().unwrap_or(0);
+
+  nodes.par_iter().filter_map(|node_id|
+  {
+    let node = tree.get_node_from_id(*node_id)?;
+    let found = match_data_literals(&node, &automaton, literals.len(), max_len)?;
+
+    let mut offset = 0;
+    let mut matched = Vec::new();
+    for rule in rules
+    {
+      let rule_found = &found[offset..offset + rule.patterns.len()];
+      if rule.condition.eval(rule, rule_found)
+      {
+        matched.push(rule.name.clone());
+      }
+      offset += rule.patterns.len();
+    }
+
+    match matched.is_empty()
+    {
+      true => None,
+      false => Some((*node_id, matched)),
+    }
+  }).collect()
+}
+
+//return which literals were found in node data, None if data can't be read
+fn match_data_literals(node : &Node, automaton : &AhoCorasick, count : usize, max_len : usize) -> Option<Vec<bool>>
+{
+  let data = node.value().get_value("data")?;
+  let builder = data.try_as_vfile_builder()?;
+  let mut file = builder.open().ok()?;
+
+  //keep the end of the previous read so literals spanning two reads are found
+  let overlap = max_len.saturating_sub(1);
+  let mut buff = vec![0; 4096 + overlap];
+  let mut kept = 0;
+  let mut readed = 0;
+  let file_size = builder.size();
+  let mut found = vec![false; count];
+
+  while readed < file_size
+  {
+    let n = match file.read(&mut buff[kept..])
+    {
+      Ok(0) | Err(_) => break,
+      Ok(n) => n,
+    };
+    readed += n as u64;
+
+    let window = &buff[..kept + n];
+    for found_match in automaton.find_overlapping_iter(window)
+    {
+      found[found_match.pattern()] = true;
+    }
+    if found.iter().all(|found| *found)
+    {
+      break
+    }
+
+    let window_len = window.len();
+    kept = overlap.min(window_len);
+    buff.copy_within(window_len - kept..window_len, 0);
+  }
+
+  Some(found)
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use std::io::Cursor;
+  use std::sync::Arc;
+  use tap::value::Value;
+  use tap::vfile::{VFile, VFileBuilder};
+  use serde::Deserialize;
+
+  /// VFile builder returning a copy of `data`, so files can be searched without touching the disk.
+  #[derive(Debug, Serialize, Deserialize)]
+  struct MemoryVFileBuilder
+  {
+    data : Vec<u8>,
+  }
+
+  #[typetag::serde]
+  impl VFileBuilder for MemoryVFileBuilder
+  {
+    fn open(&self) -> anyhow::Result<Box<dyn VFile>>
+    {
+      Ok(Box::new(Cursor::new(self.data.clone())))
+    }
+
+    fn size(&self) -> u64
+    {
+      self.data.len() as u64
+    }
+  }
+
+  /// VFile builder which data can't be opened.
+  #[derive(Debug, Serialize, Deserialize)]
+  struct BrokenVFileBuilder;
+
+  #[typetag::serde]
+  impl VFileBuilder for BrokenVFileBuilder
+  {
+    fn open(&self) -> anyhow::Result<Box<dyn VFile>>
+    {
+      Err(anyhow!("Broken file"))
+    }
+
+    fn size(&self) -> u64
+    {
+      1
+    }
+  }
+
+  /// Add a node to `tree` for each of `files`, with the file content as `data` attribute.
+  fn data_nodes(tree : &Tree, files : Vec<Vec<u8>>) -> Vec<TreeNodeId>
+  {
+    files.into_iter().enumerate().map(|(index, data)|
+    {
+      let node = Node::new(format!("file{}", index));
+      node.value().add_attribute("data", Value::VFileBuilder(Arc::new(MemoryVFileBuilder{ data })), None);
+      tree.add_child(tree.root_id, node).unwrap()
+    }).collect()
+  }
+
+  /// Return `size` spaces with `needle` written at `offset`.
+  fn file_with(size : usize, needle : &[u8], offset : usize) -> Vec<u8>
+  {
+    let mut data = vec![b' '; size];
+    data[offset..offset + needle.len()].copy_from_slice(needle);
+    data
+  }
+
+  fn offsets(data : &[u8], pattern : &str, buffer_size : usize) -> Vec<u64>
+  {
+    let regex = data_regex(pattern, false, &RegexLimits::default()).unwrap();
+    reader_regex_offsets(Cursor::new(data), data.len() as u64, &regex, buffer_size).unwrap()
+  }
+
+  #[test]
+  fn regex_offsets_across_reads()
+  {
+    let data : String = (0..1500).map(|index| format!("w{} ", index)).collect();
+    let regex = data_regex(r"\w+", false, &RegexLimits::default()).unwrap();
+    let expected : Vec<u64> = regex.find_iter(data.as_bytes()).map(|found| found.start() as u64).collect();
+
+    //a word is split between two reads for most of these sizes
+    for buffer_size in [1, 7, 100, 4096, 10000]
+    {
+      assert_eq!(offsets(data.as_bytes(), r"\w+", buffer_size), expected, "buffer size {}", buffer_size);
+    }
+  }
+
+  #[test]
+  fn whole_word_keep_inline_flags()
+  {
+    let options = DataSearchOptions{ whole_word : true, ..Default::default() };
+    let regex = data_regex(&options.pattern(r"(?-u)\x61\x62", false), true, &RegexLimits::default()).unwrap();
+    assert!(regex.is_match(b"\xFFab\xFE"));
+    assert!(!regex.is_match(b"\xFFcab\xFE"));
+    let regex = data_regex(&options.pattern(r"a(?i)B", false), true, &RegexLimits::default()).unwrap();
+    assert!(regex.is_match(b" ab "));
+    assert!(!regex.is_match(b" abc "));
+  }
+
+  #[test]
+  fn regex_count_across_reads()
+  {
+    //each run of digits would be counted again from each of its positions kept between two reads
+    let data = "0123456789 ".repeat(1000);
+    assert_eq!(offsets(data.as_bytes(), r"\d+", 4096).len(), 1000);
+    assert_eq!(offsets(data.as_bytes(), r"\d", 4096).len(), 10000);
+  }
+
+  #[test]
+  fn regex_offsets_match_on_read_boundary()
+  {
+    let mut data = vec![b' '; 2 * REGEX_OVERLAP];
+    data[4094..4100].copy_from_slice(b"needle");
+    assert_eq!(offsets(&data, "needle", 4096), vec![4094]);
+    assert_eq!(offsets(&data, "needle", 4097), vec![4094]);
+    assert_eq!(offsets(&data, "needle|dle", 4096), vec![4094]);
+  }
+
+  #[test]
+  fn query_data_match_across_read_boundary()
+  {
+    let tree = Tree::new();
+    let options = DataSearchOptions::default();
+    let nodes = data_nodes(&tree, vec![file_with(3 * options.buffer_size, b"needle", options.buffer_size - 2),
+                                       file_with(3 * options.buffer_size, b"needle", 2 * options.buffer_size - 3),
+                                       file_with(3 * options.buffer_size, b"needl", options.buffer_size - 2)]);
+
+    let result = query_data(&tree, &nodes, "needle", DataMethod::Regex, &HashSet::new(), &options).unwrap();
+    assert_eq!(result, nodes[..2].to_vec());
+    let result = query_data(&tree, &nodes, "needle", DataMethod::Text, &HashSet::new(), &options).unwrap();
+    assert_eq!(result, nodes[..2].to_vec());
+  }
+
+  #[test]
+  fn offsets_use_options_and_report_errors()
+  {
+    let tree = Tree::new();
+    let options = DataSearchOptions{ attribute_name : "content".into(), case_sensitive : Some(true), ..Default::default() };
+    let nodes = data_nodes(&tree, vec![file_with(3 * options.buffer_size, b"needle", options.buffer_size - 2)]);
+    let node = Node::new("content");
+    node.value().add_attribute("content", Value::VFileBuilder(Arc::new(MemoryVFileBuilder{ data : file_with(100, b"Needle needle", 10) })), None);
+    let content = tree.add_child(tree.root_id, node).unwrap();
+    let node = Node::new("broken");
+    node.value().add_attribute("content", Value::VFileBuilder(Arc::new(BrokenVFileBuilder)), None);
+    let broken = tree.add_child(tree.root_id, node).unwrap();
+    let nodes = vec![nodes[0], content, broken];
+
+    for data_method in [DataMethod::Regex, DataMethod::Text]
+    {
+      let offsets = query_data_offsets(&tree, &nodes, "needle", data_method, &options).unwrap();
+      assert_eq!(offsets.len(), 2);
+      assert_eq!((offsets[0].0, offsets[0].1.as_ref().unwrap()), (content, &vec![17]));
+      assert_eq!(offsets[1].0, broken);
+      assert!(offsets[1].1.is_err());
+    }
+  }
+
+  #[test]
+  fn count_use_options_and_report_errors()
+  {
+    let tree = Tree::new();
+    let nodes = data_nodes(&tree, vec![b"needle\nNeedle\nneedle needle\n".to_vec(), b"no match".to_vec()]);
+    let node = Node::new("broken");
+    node.value().add_attribute("data", Value::VFileBuilder(Arc::new(BrokenVFileBuilder)), None);
+    let broken = tree.add_child(tree.root_id, node).unwrap();
+    let nodes = vec![nodes[0], nodes[1], broken];
+
+    let counts = |data_method : DataMethod, options : &DataSearchOptions| -> Vec<(TreeNodeId, Option<u64>)>
+    {
+      query_data_count(&tree, &nodes, "needle", data_method, options).unwrap().into_iter().map(|(node_id, count)| (node_id, count.ok())).collect()
+    };
+    let sensitive = DataSearchOptions{ case_sensitive : Some(true), ..Default::default() };
+    let insensitive = DataSearchOptions{ case_sensitive : Some(false), ..Default::default() };
+    assert_eq!(counts(DataMethod::Regex, &sensitive), vec![(nodes[0], Some(3)), (broken, None)]);
+    assert_eq!(counts(DataMethod::Regex, &insensitive), vec![(nodes[0], Some(4)), (broken, None)]);
+    assert_eq!(counts(DataMethod::Text, &sensitive), vec![(nodes[0], Some(2)), (broken, None)]);
+    assert_eq!(counts(DataMethod::Text, &insensitive), vec![(nodes[0], Some(3)), (broken, None)]);
+    let small = DataSearchOptions{ max_file_size : Some(10), ..Default::default() };
+    assert_eq!(counts(DataMethod::Regex, &small), vec![(broken, None)]);
+  }
+
+  #[test]
+  fn line_matches_use_options_and_report_errors()
+  {
+    let tree = Tree::new();
+    let options = DataSearchOptions{ attribute_name : "content".into(), whole_word : true, ..Default::default() };
+    let node = Node::new("text");
+    node.value().add_attribute("content", Value::VFileBuilder(Arc::new(MemoryVFileBuilder{ data : b"needles\na needle\n".to_vec() })), None);
+    let text = tree.add_child(tree.root_id, node).unwrap();
+    let node = Node::new("broken");
+    node.value().add_attribute("content", Value::VFileBuilder(Arc::new(BrokenVFileBuilder)), None);
+    let broken = tree.add_child(tree.root_id, node).unwrap();
+    let nodes = vec![text, broken];
+
+    let lines = query_data_line_context(&tree, &nodes, "needle", &options).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!((lines[0].0, lines[0].1.as_ref().unwrap()), (text, &vec![(2, "a needle".to_string())]));
+    assert_eq!(lines[1].0, broken);
+    assert!(lines[1].1.is_err());
+  }
+
+  #[test]
+  fn multi_use_options_and_report_errors()
+  {
+    let tree = Tree::new();
+    let nodes = data_nodes(&tree, vec![b"alpha\nBeta\n".to_vec(), b"ALPHA\n".to_vec()]);
+    let node = Node::new("broken");
+    node.value().add_attribute("data", Value::VFileBuilder(Arc::new(BrokenVFileBuilder)), None);
+    let broken = tree.add_child(tree.root_id, node).unwrap();
+    let nodes = vec![nodes[0], nodes[1], broken];
+
+    let multi = |data_method : DataMethod, options : &DataSearchOptions| -> Vec<(TreeNodeId, Option<Vec<usize>>)>
+    {
+      query_data_multi(&tree, &nodes, &["alpha", "beta", "Beta"], data_method, options).unwrap().into_iter().map(|(node_id, found)| (node_id, found.ok())).collect()
+    };
+    let sensitive = DataSearchOptions{ case_sensitive : Some(true), ..Default::default() };
+    let smart_case = DataSearchOptions{ smart_case : true, ..Default::default() };
+    for data_method in [DataMethod::Regex, DataMethod::Text]
+    {
+      assert_eq!(multi(data_method.clone(), &sensitive), vec![(nodes[0], Some(vec![0, 2])), (broken, None)]);
+      assert_eq!(multi(data_method, &smart_case), vec![(nodes[0], Some(vec![0, 1, 2])), (nodes[1], Some(vec![0])), (broken, None)]);
+    }
+  }
+
+  #[test]
+  fn smart_case_ignore_escapes()
+  {
+    assert!(!has_uppercase_literal(r"\S+\W\D"));
+    assert!(!has_uppercase_literal(r"\pL\p{Lu}\P{Greek}"));
+    assert!(!has_uppercase_literal(r"\xAB\x{1F4A9}\U0001F4A9"));
+    assert!(has_uppercase_literal(r"\S+Windows"));
+    assert!(has_uppercase_literal(r"\xABC"));
+    assert!(!has_uppercase_literal("windows"));
+  }
+
+  #[test]
+  fn cancelled_query_error()
+  {
+    let tree = Tree::new();
+    let nodes : Vec<TreeNodeId> = (0..10).map(|index| tree.add_child(tree.root_id, Node::new(format!("node{}", index))).unwrap()).collect();
+    let cancel = AtomicBool::new(true);
+
+    let err = query_data_cancellable(&tree, &nodes, "needle", DataMethod::Regex, &DataSearchOptions::default(), &cancel).unwrap_err();
+    assert!(err.downcast_ref::<QueryCancelled>().is_some());
+  }
 }
@@ -1,10 +1,20 @@
 //! Filtering method that apply query and Operator that can be used to filter match between query.
 
+use std::collections::{HashMap, HashSet, BTreeSet};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Not, Range};
+
 use tap::tree::{Tree, TreeNodeId};
-use tap::error::RustructError;
+use tap::node::Node;
 
+use lalrpop_util::ParseError;
+use rayon::prelude::*;
 use anyhow::{anyhow, Result};
 use crate::parser;
+use crate::attribute::{QueryType, MatchMethod, ValuePredicate, match_query, match_query_count, match_node, match_attribute_query, match_attribute_query_count, match_attribute_node, match_attribute_range, match_attribute_numeric, QueryOptions};
+#[cfg(feature = "data")]
+use crate::data::{query_data, DataMethod, DataSearchOptions};
 
 /**
  * Match query again a [nodes](tap::node::Node) list and return matching nodes. 
@@ -25,25 +35,444 @@ impl Filter
   /// Apply `query` on all nodes found in [Tree] `path` recursively and return matching nodes [Id](TreeNodeId).
   pub fn path(tree : &Tree, query : &str, path : &str) -> Result<Vec<TreeNodeId>>
   {
-    let nodes = match tree.children_rec(Some(path))
-    {
-      Some(nodes) => nodes,
-      None => return Err(anyhow!("Invalid path"))
-    };
+    let nodes = children_rec(tree, path)?;
     Filter::nodes(tree, query, &nodes)
   }
 
   /// Apply `query` on all `nodes` and return matching Node [Id](TreeNodeId).
+  /// If `query` can't be parsed the error is a [QueryParseError].
   #[allow(clippy::ptr_arg)]
   pub fn nodes(tree : &Tree, query : &str, nodes : &Vec<TreeNodeId>) -> Result<Vec<TreeNodeId>>
   {
-    parser::OpNodesParser::new().parse(tree, nodes, query).map_err(|error| RustructError::Unknown(error.to_string()).into())
+    QueryExpr::parse(query)?.eval(tree, nodes)
+  }
+
+  /// Return if the node `node_id` match `query`, the query is evaluated on this node only without the multithreading overhead of [Filter::nodes].
+  /// If `query` can't be parsed the error is a [QueryParseError].
+  pub fn node(tree : &Tree, query : &str, node_id : TreeNodeId) -> Result<bool>
+  {
+    QueryExpr::parse(query)?.is_match(tree, node_id)
+  }
+
+  /// Same as [Filter::tree] but return matching nodes [Id](TreeNodeId) with their [Node].
+  pub fn tree_nodes(tree : &Tree, query : &str) -> Result<Vec<(TreeNodeId, Node)>>
+  {
+    Ok(Filter::resolve(tree, Filter::tree(tree, query)?))
+  }
+
+  /// Same as [Filter::path] but return matching nodes [Id](TreeNodeId) with their [Node].
+  pub fn path_nodes(tree : &Tree, query : &str, path : &str) -> Result<Vec<(TreeNodeId, Node)>>
+  {
+    Ok(Filter::resolve(tree, Filter::path(tree, query, path)?))
+  }
+
+  /// Apply `query` on all nodes in [Tree] recursively and return the number of matching nodes, see [QueryExpr::count].
+  pub fn tree_count(tree : &Tree, query : &str) -> Result<usize>
+  {
+    let nodes = tree.children_rec(None).unwrap();
+    QueryExpr::parse(query)?.count(tree, &nodes)
   }
 
+  /// Apply `query` on all `nodes` and return the number of matching nodes, duplicated nodes are only counted once.
+  #[allow(clippy::ptr_arg)]
+  pub fn nodes_count(tree : &Tree, query : &str, nodes : &Vec<TreeNodeId>) -> Result<usize>
+  {
+    let mut nodes = nodes.clone();
+    nodes.sort_unstable();
+    nodes.dedup();
+    QueryExpr::parse(query)?.count(tree, &nodes)
+  }
+
+  /// Explain why the node `node_id` match or doesn't match `query`, by returning the result of each subquery on this node.
+  /// If `query` can't be parsed the error is a [QueryParseError].
+  pub fn explain(tree : &Tree, query : &str, node_id : TreeNodeId) -> Result<QueryExplanation>
+  {
+    QueryExpr::parse(query)?.explain(tree, node_id)
+  }
+
+  /// Return each [Id](TreeNodeId) of `ids` with its [Node], ids that can't be found in [Tree] are omitted.
+  pub fn resolve(tree : &Tree, ids : Vec<TreeNodeId>) -> Vec<(TreeNodeId, Node)>
+  {
+    ids.into_iter().filter_map(|id| tree.get_node_from_id(id).map(|node| (id, node))).collect()
+  }
+
+  /// Return each [Id](TreeNodeId) of `ids` with the full path of its node in [Tree], in the same order, ids that can't be found are omitted.
+  /// Paths are built in parallel.
+  pub fn resolve_paths(tree : &Tree, ids : &[TreeNodeId]) -> Vec<(TreeNodeId, String)>
+  {
+    ids.par_iter().filter_map(|id| tree.node_path(*id).map(|path| (*id, path))).collect()
+  }
+}
+
+/// Return all the nodes found recursively from `path`, 
+/// if there is none the error tell if `path` is empty, doesn't exist in `tree` or exists but has no descendants.
+pub(crate) fn children_rec(tree : &Tree, path : &str) -> Result<Vec<TreeNodeId>>
+{
+  match tree.children_rec(Some(path))
+  {
+    Some(nodes) => Ok(nodes),
+    None if path.is_empty() => Err(anyhow!("Invalid path : path is empty")),
+    None => match tree.get_node_id(path)
+    {
+      Some(_) => Err(anyhow!("Invalid path : {} exists but has no descendants", path)),
+      None => Err(anyhow!("Invalid path : {} does not exist", path)),
+    },
+  }
+}
+
+/**
+ * Result of a [QueryExpr] and of each of its subexpressions on a node, returned by [QueryExpr::explain].
+ */
+#[derive(Debug, Clone)]
+pub struct QueryExplanation
+{
+  /// The operator (`and`, `or`, ...) or the leaf expression formatted with [Debug].
+  pub expr : String,
+  /// If the node matched this expression.
+  pub matched : bool,
+  /// Explanation of the operands of an operator, empty for a leaf expression.
+  pub children : Vec<QueryExplanation>,
 }
 
 /**
- * Implement operator (or, and, and not) for [Vec]<[TreeNodeId]>.
+ * Query built programmatically or parsed from a query string, evaluated against a list of nodes using the `match_*` and `query_*` functions.
+ *
+ * `QueryExpr::name(MatchMethod::Wildcard, "*.jpg").and(QueryExpr::attribute("exif.primary.model", MatchMethod::Fixed, "ABC", MatchMethod::Fixed))`
+ */
+#[derive(Debug, Clone)]
+pub enum QueryExpr
+{
+  /// Match node name, path or attribute names using [match_query].
+  Query{ query_type : QueryType, method : MatchMethod, value : String },
+  /// Match attribute name and value using [match_attribute_query].
+  Attribute{ name : String, name_method : MatchMethod, value : String, value_method : MatchMethod },
+  /// Match attribute value in a range using [match_attribute_range].
+  AttributeRange{ name : String, name_method : MatchMethod, min : Option<String>, max : Option<String> },
+  /// Compare attribute numeric value using [match_attribute_numeric].
+  AttributeNumeric{ name : String, name_method : MatchMethod, predicate : ValuePredicate, value : String },
+  /// Search nodes data using [query_data].
+  #[cfg(feature = "data")]
+  Data{ method : DataMethod, value : String },
+  /// Nodes not matching the expression.
+  Not(Box<QueryExpr>),
+  /// Nodes matching both expressions.
+  And(Box<QueryExpr>, Box<QueryExpr>),
+  /// Nodes matching the first expression but not the second one.
+  AndNot(Box<QueryExpr>, Box<QueryExpr>),
+  /// Nodes matching any of the expressions.
+  Or(Box<QueryExpr>, Box<QueryExpr>),
+  /// Nodes matching only one of the expressions.
+  Xor(Box<QueryExpr>, Box<QueryExpr>),
+}
+
+impl QueryExpr
+{
+  /// Parse `query` string into a [QueryExpr], return a [QueryParseError] if it can't be parsed.
+  pub fn parse(query : &str) -> Result<QueryExpr>
+  {
+    parser::OpNodesParser::new().parse(query).map_err(|error| QueryParseError::from(error).into())
+  }
+
+  /// Match `query_type` with `value` using `method`.
+  pub fn query(query_type : QueryType, method : MatchMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::Query{ query_type, method, value : value.into() }
+  }
+
+  /// Match node name with `value` using `method`.
+  pub fn name(method : MatchMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::query(QueryType::Name, method, value)
+  }
+
+  /// Match node path with `value` using `method`.
+  pub fn path(method : MatchMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::query(QueryType::Path, method, value)
+  }
+
+  /// Match attribute dotted names with `value` using `method`.
+  pub fn attribute_name(method : MatchMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::query(QueryType::AttributeName, method, value)
+  }
+
+  /// Match attribute values converted to string with `value` using `method`, whatever the attribute name.
+  pub fn attribute_value(method : MatchMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::query(QueryType::AttributeValue, method, value)
+  }
+
+  /// Match attribute which dotted name match `name` using `name_method` and which value match `value` using `value_method`.
+  pub fn attribute(name : &str, name_method : MatchMethod, value : &str, value_method : MatchMethod) -> QueryExpr
+  {
+    QueryExpr::Attribute{ name : name.into(), name_method, value : value.into(), value_method }
+  }
+
+  /// Match attribute which dotted name match `name` using `name_method` and which value is between `min` and `max`.
+  pub fn attribute_range(name : &str, name_method : MatchMethod, min : Option<&str>, max : Option<&str>) -> QueryExpr
+  {
+    QueryExpr::AttributeRange{ name : name.into(), name_method, min : min.map(String::from), max : max.map(String::from) }
+  }
+
+  /// Match attribute which dotted name match `name` using `name_method` and which numeric value match `predicate` with `value`.
+  pub fn attribute_numeric(name : &str, name_method : MatchMethod, predicate : ValuePredicate, value : &str) -> QueryExpr
+  {
+    QueryExpr::AttributeNumeric{ name : name.into(), name_method, predicate, value : value.into() }
+  }
+
+  /// Search `value` in nodes data using `method`.
+  #[cfg(feature = "data")]
+  pub fn data(method : DataMethod, value : &str) -> QueryExpr
+  {
+    QueryExpr::Data{ method, value : value.into() }
+  }
+
+  /// Nodes matching self and `other`.
+  pub fn and(self, other : QueryExpr) -> QueryExpr
+  {
+    QueryExpr::And(Box::new(self), Box::new(other))
+  }
+
+  /// Nodes matching self but not `other`.
+  /// The `and not` operator of the query string is reversed : `a and not b` match nodes matching `b` but not `a` (see [Op::and_not]).
+  pub fn and_not(self, other : QueryExpr) -> QueryExpr
+  {
+    QueryExpr::AndNot(Box::new(self), Box::new(other))
+  }
+
+  /// Nodes matching self or `other`.
+  pub fn or(self, other : QueryExpr) -> QueryExpr
+  {
+    QueryExpr::Or(Box::new(self), Box::new(other))
+  }
+
+  /// Nodes matching only self or only `other`.
+  pub fn xor(self, other : QueryExpr) -> QueryExpr
+  {
+    QueryExpr::Xor(Box::new(self), Box::new(other))
+  }
+
+  /// Return true if the expression search in nodes data.
+  pub fn has_data(&self) -> bool
+  {
+    match self
+    {
+      #[cfg(feature = "data")]
+      QueryExpr::Data{ .. } => true,
+      QueryExpr::Not(expr) => expr.has_data(),
+      QueryExpr::And(left, right) | QueryExpr::AndNot(left, right) | QueryExpr::Or(left, right) | QueryExpr::Xor(left, right) => left.has_data() || right.has_data(),
+      _ => false,
+    }
+  }
+
+  /// Evaluate the expression on `nodes` and return matching nodes [Id](TreeNodeId).
+  #[allow(clippy::ptr_arg)]
+  pub fn eval(&self, tree : &Tree, nodes : &Vec<TreeNodeId>) -> Result<Vec<TreeNodeId>>
+  {
+    match self
+    {
+      QueryExpr::Query{ query_type, method, value } => match_query(tree, nodes, query_type.clone(), method.clone(), value, &QueryOptions::default()),
+      QueryExpr::Attribute{ name, name_method, value, value_method } => match_attribute_query(tree, nodes, name, name_method.clone(), value, value_method.clone(), &QueryOptions::default()),
+      QueryExpr::AttributeRange{ name, name_method, min, max } => match_attribute_range(tree, nodes, name, name_method.clone(), min.as_deref(), max.as_deref()),
+      QueryExpr::AttributeNumeric{ name, name_method, predicate, value } => match_attribute_numeric(tree, nodes, name, name_method.clone(), predicate.clone(), value),
+      #[cfg(feature = "data")]
+      QueryExpr::Data{ method, value } => query_data(tree, nodes, value, method.clone(), &HashSet::new(), &DataSearchOptions::default()),
+      //the complement is relative to `nodes` not to the whole tree
+      QueryExpr::Not(expr) => Ok(Op::and_not(expr.eval(tree, nodes)?, nodes.clone())),
+      //each expression match nodes independently, so the second expression only need to be evaluated on the nodes matched by the first one,
+      //data search is expensive so it's evaluated last on the narrowed nodes
+      QueryExpr::And(left, right) =>
+      {
+        let (first, second) = match left.has_data() && !right.has_data()
+        {
+          true => (right, left),
+          false => (left, right),
+        };
+        let matched = first.eval(tree, nodes)?;
+        Ok(Op::and(second.eval(tree, &matched)?, matched))
+      },
+      QueryExpr::AndNot(left, right) =>
+      {
+        let matched = left.eval(tree, nodes)?;
+        Ok(Op::and_not(right.eval(tree, &matched)?, matched))
+      },
+      QueryExpr::Or(left, right) => Ok(Op::or(left.eval(tree, nodes)?, right.eval(tree, nodes)?)),
+      QueryExpr::Xor(left, right) => Ok(Op::xor(left.eval(tree, nodes)?, right.eval(tree, nodes)?)),
+    }
+  }
+
+  /// Evaluate the expression on `nodes` and return the number of matching nodes, `nodes` must not contain duplicates.
+  /// Leaf queries and the last one of an intersection are counted without collecting and sorting the matching ids.
+  #[allow(clippy::ptr_arg)]
+  pub fn count(&self, tree : &Tree, nodes : &Vec<TreeNodeId>) -> Result<usize>
+  {
+    match self
+    {
+      QueryExpr::Query{ query_type, method, value } => match_query_count(tree, nodes, query_type.clone(), method.clone(), value, &QueryOptions::default()),
+      QueryExpr::Attribute{ name, name_method, value, value_method } => match_attribute_query_count(tree, nodes, name, name_method.clone(), value, value_method.clone(), &QueryOptions::default()),
+      //nodes are unique so the complement can be counted by difference
+      QueryExpr::Not(expr) => Ok(nodes.len() - expr.count(tree, nodes)?),
+      QueryExpr::And(left, right) =>
+      {
+        let (first, second) = match left.has_data() && !right.has_data()
+        {
+          true => (right, left),
+          false => (left, right),
+        };
+        let matched = first.eval(tree, nodes)?;
+        second.count(tree, &matched)
+      },
+      QueryExpr::AndNot(left, right) =>
+      {
+        let matched = left.eval(tree, nodes)?;
+        Ok(matched.len() - right.count(tree, &matched)?)
+      },
+      _ => Ok(self.eval(tree, nodes)?.len()),
+    }
+  }
+
+  /// Return the result of the expression and of each of its subexpressions on the node `node_id`, 
+  /// unlike [QueryExpr::is_match] all the subexpressions are evaluated even if the result is already known.
+  pub fn explain(&self, tree : &Tree, node_id : TreeNodeId) -> Result<QueryExplanation>
+  {
+    let operator = |expr : &str, children : Vec<QueryExplanation>, matched : fn(&[QueryExplanation]) -> bool|
+      QueryExplanation{ expr : expr.into(), matched : matched(&children), children };
+
+    Ok(match self
+    {
+      QueryExpr::Not(expr) => operator("not", vec![expr.explain(tree, node_id)?], |children| !children[0].matched),
+      QueryExpr::And(left, right) => operator("and", vec![left.explain(tree, node_id)?, right.explain(tree, node_id)?], |children| children[0].matched && children[1].matched),
+      QueryExpr::AndNot(left, right) => operator("and not", vec![left.explain(tree, node_id)?, right.explain(tree, node_id)?], |children| children[0].matched && !children[1].matched),
+      QueryExpr::Or(left, right) => operator("or", vec![left.explain(tree, node_id)?, right.explain(tree, node_id)?], |children| children[0].matched || children[1].matched),
+      QueryExpr::Xor(left, right) => operator("xor", vec![left.explain(tree, node_id)?, right.explain(tree, node_id)?], |children| children[0].matched != children[1].matched),
+      _ => QueryExplanation{ expr : format!("{:?}", self), matched : self.is_match(tree, node_id)?, children : Vec::new() },
+    })
+  }
+
+  /// Return if the node `node_id` match the expression, like [QueryExpr::eval] on a single node but without iterating in parallel.
+  pub fn is_match(&self, tree : &Tree, node_id : TreeNodeId) -> Result<bool>
+  {
+    match self
+    {
+      QueryExpr::Query{ query_type, method, value } => match_node(tree, node_id, query_type.clone(), method.clone(), value),
+      QueryExpr::Attribute{ name, name_method, value, value_method } => match_attribute_node(tree, node_id, name, name_method.clone(), value, value_method.clone()),
+      //range, numeric and data queries have no single node version, they're evaluated on a one node list
+      QueryExpr::AttributeRange{ .. } | QueryExpr::AttributeNumeric{ .. } => Ok(!self.eval(tree, &vec![node_id])?.is_empty()),
+      #[cfg(feature = "data")]
+      QueryExpr::Data{ .. } => Ok(!self.eval(tree, &vec![node_id])?.is_empty()),
+      QueryExpr::Not(expr) => Ok(!expr.is_match(tree, node_id)?),
+      //data search is expensive so it's evaluated last and skipped if the other side doesn't match
+      QueryExpr::And(left, right) =>
+      {
+        let (first, second) = match left.has_data() && !right.has_data()
+        {
+          true => (right, left),
+          false => (left, right),
+        };
+        Ok(first.is_match(tree, node_id)? && second.is_match(tree, node_id)?)
+      },
+      QueryExpr::AndNot(left, right) => Ok(left.is_match(tree, node_id)? && !right.is_match(tree, node_id)?),
+      QueryExpr::Or(left, right) => Ok(left.is_match(tree, node_id)? || right.is_match(tree, node_id)?),
+      QueryExpr::Xor(left, right) => Ok(left.is_match(tree, node_id)? != right.is_match(tree, node_id)?),
+    }
+  }
+}
+
+impl Not for QueryExpr
+{
+  type Output = QueryExpr;
+
+  /// Nodes not matching self.
+  fn not(self) -> QueryExpr
+  {
+    QueryExpr::Not(Box::new(self))
+  }
+}
+
+/**
+ * Unescape `\'`, `\\`, `\n` and `\t` in a quoted string of a query, return an error for any other escape sequence.
+ */
+pub(crate) fn unescape(quoted : &str) -> std::result::Result<String, &'static str>
+{
+  let mut result = String::with_capacity(quoted.len());
+  let mut chars = quoted.chars();
+
+  while let Some(c) = chars.next()
+  {
+    if c != '\\'
+    {
+      result.push(c);
+      continue
+    }
+    match chars.next()
+    {
+      Some('\'') => result.push('\''),
+      Some('\\') => result.push('\\'),
+      Some('n') => result.push('\n'),
+      Some('t') => result.push('\t'),
+      _ => return Err("Invalid escape sequence in quoted string"),
+    }
+  }
+  Ok(result)
+}
+
+/**
+ * Return `nodes` sorted in the order they appear in a depth-first traversal of the [Tree] (the order of [Tree::children_rec]).
+ * Nodes that can't be found in the [Tree] are put at the end.
+ */
+pub fn sort_by_tree_order(tree : &Tree, nodes : &[TreeNodeId]) -> Vec<TreeNodeId>
+{
+  let positions : HashMap<TreeNodeId, usize> = tree.children_rec(None).unwrap_or_default().into_iter().enumerate().map(|(position, id)| (id, position)).collect();
+
+  let mut result = nodes.to_vec();
+  result.sort_by_key(|id| positions.get(id).copied().unwrap_or(usize::MAX));
+  result
+}
+
+/**
+ * Error returned when a query can't be parsed, keep the position of the error in the query.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParseError
+{
+  /// Description of the error.
+  pub message : String,
+  /// Byte range of the query where the error was found, empty if the error has no position (end of the query or error returned by a query).
+  pub span : Range<usize>,
+  /// Tokens that were expected at `span`.
+  pub expected : Vec<String>,
+}
+
+impl<T : fmt::Display, E : fmt::Display> From<ParseError<usize, T, E>> for QueryParseError
+{
+  fn from(error : ParseError<usize, T, E>) -> Self
+  {
+    let message = error.to_string();
+    let (span, expected) = match error
+    {
+      ParseError::InvalidToken{ location } => (location..location, Vec::new()),
+      ParseError::UnrecognizedEOF{ location, expected } => (location..location, expected),
+      ParseError::UnrecognizedToken{ token : (start, _, end), expected } => (start..end, expected),
+      ParseError::ExtraToken{ token : (start, _, end) } => (start..end, Vec::new()),
+      ParseError::User{ .. } => (0..0, Vec::new()),
+    };
+    QueryParseError{ message, span, expected }
+  }
+}
+
+impl fmt::Display for QueryParseError
+{
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl std::error::Error for QueryParseError {}
+
+/**
+ * Implement operator (or, and, and not, xor) for [Vec]<[TreeNodeId]>.
  */
 pub struct Op
 {
@@ -54,45 +483,219 @@ impl Op
   /// Apply and not operator for all element of `left` to elements of `right` and return matching nodes [Id](TreeNodeId).
   pub fn and_not(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
   {
-    let mut result = Vec::new();
+    let left : HashSet<TreeNodeId> = left.into_iter().collect();
+    let mut result : Vec<TreeNodeId> = right.into_iter().filter(|id| !left.contains(id)).collect();
 
-    for id in right
+    result.sort_unstable();
+    result.dedup();
+    result
+  }
+
+  /// Apply and operator for all element of `left` vec to elements of `right` and return matching nodes [Id](TreeNodeId). 
+  pub fn and(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  {
+    let left : HashSet<TreeNodeId> = left.into_iter().collect();
+    let mut result : Vec<TreeNodeId> = right.into_iter().filter(|id| left.contains(id)).collect();
+
+    result.sort_unstable();
+    result.dedup();
+    result
+  }
+
+  /// Apply xor operator for all element of `left` vec to elements of `right` and return nodes [Id](TreeNodeId) found in only one of them.
+  pub fn xor(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  {
+    let left : BTreeSet<TreeNodeId> = left.into_iter().collect();
+    let right : BTreeSet<TreeNodeId> = right.into_iter().collect();
+
+    left.symmetric_difference(&right).copied().collect()
+  }
+
+  /// Apply or operator for all element of `left` vec to elements of `right` and return matching nodes [Id](TreeNodeId). 
+  pub fn or(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  {
+    let result : BTreeSet<TreeNodeId> = left.into_iter().chain(right).collect();
+    result.into_iter().collect()
+  }
+
+  /// Apply and operator to all the `sets` and return sorted nodes [Id](TreeNodeId) found in every set, empty if there is no set.
+  /// Sets are intersected from the smallest to the biggest so the intermediate result is as small as possible.
+  pub fn and_all(mut sets : Vec<Vec<TreeNodeId>>) -> Vec<TreeNodeId>
+  {
+    sets.sort_unstable_by_key(|set| set.len());
+    let mut sets = sets.into_iter();
+
+    let mut result : HashSet<TreeNodeId> = match sets.next()
+    {
+      Some(smallest) => smallest.into_iter().collect(),
+      None => return Vec::new(),
+    };
+    for set in sets
     {
-      if !left.contains(&id)
+      if result.is_empty()
       {
-        result.push(id);
+        break;
       }
+      let set : HashSet<TreeNodeId> = set.into_iter().collect();
+      result.retain(|id| set.contains(id));
     }
-    result.sort();
-    result.dedup();
+
+    let mut result : Vec<TreeNodeId> = result.into_iter().collect();
+    result.sort_unstable();
     result
   }
 
-  /// Apply and operator for all element of `left` vec to elements of `right` and return matching nodes [Id](TreeNodeId). 
-  pub fn and(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  /// Apply or operator to all the `sets` and return sorted nodes [Id](TreeNodeId) found in any set.
+  pub fn or_all(sets : Vec<Vec<TreeNodeId>>) -> Vec<TreeNodeId>
   {
-    let mut result = Vec::new();
+    let result : BTreeSet<TreeNodeId> = sets.into_iter().flatten().collect();
+    result.into_iter().collect()
+  }
 
-    for id in right
+  /// Same as [Op::and] but merge `left` and `right` in linear time without hashing nor sorting.
+  /// `left` and `right` must be sorted and unique (like the results of the `match_*` functions), the result is sorted and unique.
+  pub fn and_sorted(left : &[TreeNodeId], right : &[TreeNodeId]) -> Vec<TreeNodeId>
+  {
+    debug_assert!(Op::is_sorted_unique(left) && Op::is_sorted_unique(right));
+
+    let mut result = Vec::with_capacity(left.len().min(right.len()));
+    let (mut l, mut r) = (0, 0);
+    while l < left.len() && r < right.len()
     {
-      if left.contains(&id)
+      match left[l].cmp(&right[r])
       {
-        result.push(id); 
+        Ordering::Less => l += 1,
+        Ordering::Greater => r += 1,
+        Ordering::Equal =>
+        {
+          result.push(left[l]);
+          l += 1;
+          r += 1;
+        },
       }
     }
-    result.sort();
-    result.dedup();
     result
   }
 
-  /// Apply or operator for all element of `left` vec to elements of `right` and return matching nodes [Id](TreeNodeId). 
-  pub fn or(left : Vec<TreeNodeId>, right : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  /// Same as [Op::or] but merge `left` and `right` in linear time, they must be sorted and unique like for [Op::and_sorted].
+  pub fn or_sorted(left : &[TreeNodeId], right : &[TreeNodeId]) -> Vec<TreeNodeId>
   {
-    let mut result = left;
+    debug_assert!(Op::is_sorted_unique(left) && Op::is_sorted_unique(right));
 
-    result.extend(right);
-    result.sort();
-    result.dedup();
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let (mut l, mut r) = (0, 0);
+    while l < left.len() && r < right.len()
+    {
+      match left[l].cmp(&right[r])
+      {
+        Ordering::Less =>
+        {
+          result.push(left[l]);
+          l += 1;
+        },
+        Ordering::Greater =>
+        {
+          result.push(right[r]);
+          r += 1;
+        },
+        Ordering::Equal =>
+        {
+          result.push(left[l]);
+          l += 1;
+          r += 1;
+        },
+      }
+    }
+    result.extend_from_slice(&left[l..]);
+    result.extend_from_slice(&right[r..]);
+    result
+  }
+
+  /// Same as [Op::and_not] (elements of `right` not found in `left`) but merge `left` and `right` in linear time, 
+  /// they must be sorted and unique like for [Op::and_sorted].
+  pub fn and_not_sorted(left : &[TreeNodeId], right : &[TreeNodeId]) -> Vec<TreeNodeId>
+  {
+    debug_assert!(Op::is_sorted_unique(left) && Op::is_sorted_unique(right));
+
+    let mut result = Vec::with_capacity(right.len());
+    let mut l = 0;
+    for id in right
+    {
+      while l < left.len() && left[l] < *id
+      {
+        l += 1;
+      }
+      if l == left.len() || left[l] != *id
+      {
+        result.push(*id);
+      }
+    }
     result
   }
+
+  fn is_sorted_unique(ids : &[TreeNodeId]) -> bool
+  {
+    ids.windows(2).all(|pair| pair[0] < pair[1])
+  }
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  /// Return the ids of a tree with `count` nodes.
+  fn node_ids(count : usize) -> Vec<TreeNodeId>
+  {
+    let tree = Tree::new();
+    (0..count).map(|index| tree.add_child(tree.root_id, Node::new(format!("node{}", index))).unwrap()).collect()
+  }
+
+  /// Return unsorted ids with duplicates, taken from `ids` with a pseudo random step.
+  fn pick(ids : &[TreeNodeId], step : usize, count : usize) -> Vec<TreeNodeId>
+  {
+    (0..count).map(|index| ids[(index * step) % ids.len()]).collect()
+  }
+
+  fn sorted(mut ids : Vec<TreeNodeId>) -> Vec<TreeNodeId>
+  {
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+  }
+
+  #[test]
+  fn sorted_ops_match_ops()
+  {
+    let ids = node_ids(1000);
+    let inputs = [(pick(&ids, 7, 600), pick(&ids, 13, 800)), (pick(&ids, 3, 50), pick(&ids, 11, 1500)), (Vec::new(), pick(&ids, 17, 300))];
+
+    for (left, right) in inputs
+    {
+      let (sorted_left, sorted_right) = (sorted(left.clone()), sorted(right.clone()));
+
+      let and = Op::and(left.clone(), right.clone());
+      assert!(Op::is_sorted_unique(&and));
+      assert_eq!(Op::and_sorted(&sorted_left, &sorted_right), and);
+
+      let or = Op::or(left.clone(), right.clone());
+      assert!(Op::is_sorted_unique(&or));
+      assert_eq!(Op::or_sorted(&sorted_left, &sorted_right), or);
+
+      let and_not = Op::and_not(left.clone(), right.clone());
+      assert!(Op::is_sorted_unique(&and_not));
+      assert_eq!(Op::and_not_sorted(&sorted_left, &sorted_right), and_not);
+      assert_eq!(Op::and_not_sorted(&sorted_right, &sorted_left), Op::and_not(right, left));
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  #[cfg(debug_assertions)]
+  fn sorted_ops_check_precondition()
+  {
+    let ids = node_ids(10);
+    let unsorted = vec![ids[3], ids[1]];
+    Op::and_sorted(&unsorted, &ids);
+  }
 }
@@ -9,5 +9,8 @@
 pub mod filter;  
 pub mod attribute;
 pub mod timeline;
+#[cfg(feature = "data")]
 pub mod data;
+mod progress;
+pub use progress::QueryCancelled;
 lalrpop_mod!(pub parser);
@@ -0,0 +1,87 @@
+//! Report the progress of a multithreaded query to the caller thread or stop it once enough results were found.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tap::tree::TreeNodeId;
+
+/// Time to wait between two calls of the progress callback.
+const PROGRESS_INTERVAL : Duration = Duration::from_millis(100);
+
+/// Run `search` on a new thread and call `progress` from the current thread with the number of nodes processed until the search is finished.
+/// `progress` is always called a last time once `search` returned.
+pub(crate) fn poll_progress<T, S, F>(search : S, mut progress : F) -> T
+  where T : Send, S : FnOnce(&AtomicUsize) -> T + Send, F : FnMut(usize)
+{
+  let processed = AtomicUsize::new(0);
+
+  std::thread::scope(|scope|
+  {
+    let handle = scope.spawn(|| search(&processed));
+    while !handle.is_finished()
+    {
+      progress(processed.load(Ordering::Relaxed));
+      std::thread::sleep(PROGRESS_INTERVAL);
+    }
+    progress(processed.load(Ordering::Relaxed));
+    handle.join().unwrap_or_else(|err| std::panic::resume_unwind(err))
+  })
+}
+
+/**
+ *  Count the results found by the rayon workers, so they can stop searching once `limit` results were found.
+ *  Workers already searching a node when the limit is reached still return their result,
+ *  so more than `limit` results can be collected and must be passed to [truncate](ResultLimit::truncate).
+ */
+pub(crate) struct ResultLimit
+{
+  limit : Option<usize>,
+  found : AtomicUsize,
+}
+
+impl ResultLimit
+{
+  pub(crate) fn new(limit : Option<usize>) -> Self
+  {
+    ResultLimit{ limit, found : AtomicUsize::new(0) }
+  }
+
+  /// Return true if `limit` results were already found, always false if there is no limit.
+  pub(crate) fn is_reached(&self) -> bool
+  {
+    self.limit.map_or(false, |limit| self.found.load(Ordering::Relaxed) >= limit)
+  }
+
+  /// Must be called each time a result is found.
+  pub(crate) fn found(&self)
+  {
+    if self.limit.is_some()
+    {
+      self.found.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Keep at most `limit` results.
+  pub(crate) fn truncate(&self, results : &mut Vec<TreeNodeId>)
+  {
+    if let Some(limit) = self.limit
+    {
+      results.truncate(limit);
+    }
+  }
+}
+
+/// Error returned by the cancellable queries when the search was cancelled.
+#[derive(Debug)]
+pub struct QueryCancelled;
+
+impl fmt::Display for QueryCancelled
+{
+  fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(f, "Query cancelled")
+  }
+}
+
+impl std::error::Error for QueryCancelled {}
@@ -1,24 +1,55 @@
 //! Generate a timeline from a vector of [nodes](Node).
 
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use tap::node::Node;
 use tap::value::ValueTypeId;
 use tap::attribute::Attribute;
 use tap::tree::{Tree, TreeNodeId};
 
 use serde::Serialize;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, FixedOffset, SecondsFormat, TimeZone, Utc};
 use rayon::prelude::*;
+use rayon::ThreadPool;
 use anyhow::{anyhow, Result};
 
-/// Contain `time` a [DateTime] [value](tap::value::Value) of the [Attribute] named `attribute_name` found in node `id`.
+use crate::filter::children_rec;
+use crate::attribute::child_dotted_name;
+use crate::progress::QueryCancelled;
+
+/// Contain `time` a [DateTime] [value](tap::value::Value) of the [Attribute] named `attribute_name` found in node `id` named `name`.
 #[derive(Serialize)]
 pub struct TimeInfo 
 {
   pub time : DateTime<Utc>,
   pub attribute_name : String,
   pub id : TreeNodeId,
+  pub name : String,
+}
+
+impl TimeInfo
+{
+  /// Return `time` converted to `offset` for display, the timeline is always sorted and filtered in UTC.
+  pub fn time_with_offset(&self, offset : &FixedOffset) -> DateTime<FixedOffset>
+  {
+    self.time.with_timezone(offset)
+  }
+}
+
+/// Same as [TimeInfo] but with `time` converted to a fixed offset, used to serialize a timeline in local time.
+#[derive(Serialize)]
+struct OffsetTimeInfo<'a>
+{
+  time : DateTime<FixedOffset>,
+  attribute_name : &'a str,
+  id : TreeNodeId,
+  name : &'a str,
 }
 
+/// Maximum number of buckets returned by [Timeline::histogram].
+pub const MAX_HISTOGRAM_BUCKETS : usize = 1 << 20;
+
 /**
  *  Search for all [DateTime] [Attribute] on each [Node] of a Vector of [Node] 
  *  then return a sorted Vector of [TimeInfo] for each [DateTime] [Attribute] found on each [Node]
@@ -38,13 +69,11 @@ impl Timeline
   }
 
   /// Return a timeline as a [Vec]<[TimeInfo]> containing all [DateTime] [Attribute] which time is included between min_time and max_time for all [nodes](Node) that can be found recursively from `path`.
+  /// Return an error if `min_time` is greater than `max_time`.
   pub fn path(tree : &Tree, path : &str,  min_time : &DateTime<Utc>, max_time : &DateTime<Utc>) -> Result<Vec<TimeInfo>>
   {
-    let nodes = match tree.children_rec(Some(path))
-    {
-      Some(nodes) => nodes,
-      None => return Err(anyhow!("Invalid path"))
-    };
+    Timeline::check_range(min_time, max_time)?;
+    let nodes = children_rec(tree, path)?;
     Ok(Timeline::nodes(&tree, &nodes, min_time, max_time))
   }
 
@@ -63,45 +92,248 @@ impl Timeline
       }
     }).flatten().collect();
 
-    times.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+    times.par_sort_unstable_by(|a, b| a.time.cmp(&b.time));
+    times
+  }
+
+  /// Same as [Timeline::nodes] but run inside `pool` rather than the global rayon thread pool.
+  pub fn nodes_in_pool(pool : &ThreadPool, tree : &Tree, nodes : &Vec<TreeNodeId>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>) -> Vec<TimeInfo>
+  {
+    pool.install(|| Timeline::nodes(tree, nodes, min_time, max_time))
+  }
+
+  /// Return an [Iterator] over the [TimeInfo] of `nodes` in the order of `nodes` (not sorted by time).
+  /// Nodes are processed lazily one by one in the caller thread so only the [TimeInfo] of the current node are kept in memory,
+  /// use it to stream a timeline to an external sorter or a database, [Timeline::nodes] must hold the full timeline in memory to sort it.
+  pub fn nodes_unsorted<'a>(tree : &'a Tree, nodes : &'a [TreeNodeId], min_time : &'a DateTime<Utc>, max_time : &'a DateTime<Utc>) -> impl Iterator<Item = TimeInfo> + 'a
+  {
+    nodes.iter().filter_map(move |node_id|
+    {
+      let node = tree.get_node_from_id(*node_id)?;
+      Some(Timeline::match_time(&node, node_id, min_time, max_time))
+    }).flatten()
+  }
+
+  /// Return the page of the timeline of `nodes` starting at `offset` containing up to `limit` [TimeInfo] and the total number of [TimeInfo] in the timeline.
+  /// Only the entries before the end of the page are sorted, [TimeInfo] with the same time are ordered by id then attribute name so pages are stable.
+  pub fn nodes_paged(tree : &Tree, nodes : &Vec<TreeNodeId>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>, offset : usize, limit : usize) -> (Vec<TimeInfo>, usize)
+  {
+    let mut times : Vec<TimeInfo> = nodes.par_iter().filter_map(|node_id|
+    {
+      let node = tree.get_node_from_id(*node_id)?;
+      Some(Timeline::match_time(&node, &node_id, &min_time, &max_time))
+    }).flatten().collect();
+
+    let total = times.len();
+    let end = offset.saturating_add(limit).min(total);
+    let compare = |a : &TimeInfo, b : &TimeInfo| a.time.cmp(&b.time).then(a.id.cmp(&b.id)).then_with(|| a.attribute_name.cmp(&b.attribute_name));
+
+    //move the entries of the page and the ones before it at the start of the vec without sorting the rest
+    if end < total
+    {
+      times.select_nth_unstable_by(end, compare);
+      times.truncate(end);
+    }
+    times.par_sort_unstable_by(compare);
+    times.drain(..offset.min(end));
+    (times, total)
+  }
+
+  /// Same as [Timeline::nodes] but stop early when `cancel` is set to true.
+  /// No partial timeline is returned : a [QueryCancelled] error is returned if it was cancelled, an error is also returned if `min_time` is greater than `max_time`.
+  pub fn nodes_cancellable(tree : &Tree, nodes : &Vec<TreeNodeId>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>, cancel : &AtomicBool) -> Result<Vec<TimeInfo>>
+  {
+    Timeline::check_range(min_time, max_time)?;
+    let mut times : Vec<TimeInfo> = nodes.par_iter().filter_map(|node_id|
+    {
+      if cancel.load(Ordering::Relaxed)
+      {
+        return None
+      }
+      let node = tree.get_node_from_id(*node_id)?;
+      Some(Timeline::match_time(&node, &node_id, &min_time, &max_time))
+    }).flatten().collect();
+
+    if cancel.load(Ordering::Relaxed)
+    {
+      return Err(QueryCancelled.into())
+    }
+    times.par_sort_unstable_by(|a, b| a.time.cmp(&b.time));
+    Ok(times)
+  }
+
+  /// Return an error if `min_time` is greater than `max_time`, equal times are valid and match only this instant.
+  fn check_range(min_time : &DateTime<Utc>, max_time : &DateTime<Utc>) -> Result<()>
+  {
+    match min_time <= max_time
+    {
+      true => Ok(()),
+      false => Err(anyhow!("min_time must be <= max_time")),
+    }
+  }
+
+  /// Same as [Timeline::nodes] but only keep [TimeInfo] which dotted `attribute_name` is one of `attribute_names` (`fs.mtime`).
+  pub fn nodes_filtered(tree : &Tree, nodes : &Vec<TreeNodeId>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>, attribute_names : &[String]) -> Vec<TimeInfo>
+  {
+    let mut times : Vec<TimeInfo> = nodes.par_iter().filter_map(|node_id|
+    {
+      let node = tree.get_node_from_id(*node_id)?;
+      let mut times = Timeline::match_time(&node, &node_id, &min_time, &max_time);
+      times.retain(|time| attribute_names.contains(&time.attribute_name));
+      Some(times)
+    }).flatten().collect();
+
+    times.par_sort_unstable_by(|a, b| a.time.cmp(&b.time));
     times
   }
 
+  /// Return `nodes` that have no [DateTime] [Attribute] included between `min_time` and `max_time`.
+  /// If `include_undated` is false only nodes having at least one [DateTime] [Attribute] are returned (all of them outside of the range),
+  /// if it's true nodes without any [DateTime] [Attribute] are returned too.
+  pub fn nodes_outside_range(tree : &Tree, nodes : &Vec<TreeNodeId>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>, include_undated : bool) -> Vec<TreeNodeId>
+  {
+    nodes.par_iter().filter_map(|node_id|
+    {
+      let node = tree.get_node_from_id(*node_id)?;
+      let mut times = Vec::new();
+      for attribute in node.value().attributes().iter()
+      {
+        Timeline::collect_time_rec(&attribute, &mut times);
+      }
+
+      if times.is_empty()
+      {
+        return if include_undated { Some(*node_id) } else { None }
+      }
+      if times.iter().any(|time| time >= min_time && time <= max_time)
+      {
+        return None
+      }
+      Some(*node_id)
+    }).collect()
+  }
+
+  fn collect_time_rec(attribute : &Attribute, times : &mut Vec<DateTime<Utc>>)
+  {
+    if attribute.type_id() == ValueTypeId::Attributes
+    {
+      for current_attribute in attribute.value().as_attributes().attributes().iter()
+      {
+        Timeline::collect_time_rec(&current_attribute, times)
+      }
+    }
+    else if attribute.type_id() == ValueTypeId::ReflectStruct 
+    {
+      let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
+      for current_attribute in attributes.iter() 
+      {
+        Timeline::collect_time_rec(&current_attribute, times)
+      }
+    }
+    else if attribute.type_id() == ValueTypeId::DateTime
+    {
+      times.push(attribute.value().as_date_time());
+    }
+  }
+
+  /// Remove the [TimeInfo] with the same `time`, `attribute_name` and `id` from a timeline sorted by time (like the one returned by [Timeline::nodes]),
+  /// entries with the same time are then ordered by attribute name and id. 
+  /// A [DateTime] found both in an [Attribute] and in its ReflectStruct expansion is reported twice by the timeline, this keep only one of them.
+  pub fn dedup(times : &mut Vec<TimeInfo>)
+  {
+    //times is already sorted by time so this stable sort only reorder entries with the same time
+    times.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.attribute_name.cmp(&b.attribute_name)).then_with(|| a.id.cmp(&b.id)));
+    times.dedup_by(|a, b| a.time == b.time && a.attribute_name == b.attribute_name && a.id == b.id);
+  }
+
+  /// Write `times` as CSV to `writer` with a `time,attribute_name,id,name` header, time is written in RFC 3339, attribute name and node name are quoted.
+  pub fn to_csv<W : Write>(times : &[TimeInfo], writer : &mut W) -> Result<()>
+  {
+    //a zero offset is written as Z like an UTC time
+    Timeline::to_csv_with_offset(times, writer, &FixedOffset::east(0))
+  }
+
+  /// Same as [Timeline::to_csv] but time is converted to `offset` (`+02:00`) rather than written in UTC.
+  pub fn to_csv_with_offset<W : Write>(times : &[TimeInfo], writer : &mut W, offset : &FixedOffset) -> Result<()>
+  {
+    writeln!(writer, "time,attribute_name,id,name")?;
+    for time in times
+    {
+      writeln!(writer, "{},\"{}\",{},\"{}\"", time.time_with_offset(offset).to_rfc3339_opts(SecondsFormat::AutoSi, true), time.attribute_name.replace('"', "\"\""), time.id, time.name.replace('"', "\"\""))?;
+    }
+    Ok(())
+  }
+
+  /// Serialize `times` to a JSON array with time converted to `offset`, in RFC 3339 with the offset (`2021-01-01T12:00:00+02:00`).
+  pub fn to_json_with_offset(times : &[TimeInfo], offset : &FixedOffset) -> Result<String>
+  {
+    let times : Vec<OffsetTimeInfo> = times.iter().map(|time| OffsetTimeInfo{ time : time.time_with_offset(offset), attribute_name : &time.attribute_name, id : time.id, name : &time.name }).collect();
+    Ok(serde_json::to_string(&times)?)
+  }
+
+  /// Count `times` in buckets of `bucket` width aligned to the Unix epoch and return the start of each bucket with its count,
+  /// empty buckets between the first and the last time are included. A time exactly on a bucket boundary is counted in the bucket starting at this time.
+  /// Return an empty [Vec] if `times` is empty or `bucket` is not positive, 
+  /// and an error if more than [MAX_HISTOGRAM_BUCKETS] buckets would be needed (times far apart with a small bucket).
+  pub fn histogram(times : &[TimeInfo], bucket : Duration) -> Result<Vec<(DateTime<Utc>, usize)>>
+  {
+    let bucket = bucket.num_milliseconds();
+    if bucket <= 0
+    {
+      return Ok(Vec::new())
+    }
+
+    let indexes : Vec<i64> = times.iter().map(|time| time.time.timestamp_millis().div_euclid(bucket)).collect();
+    let (first, last) = match (indexes.iter().min(), indexes.iter().max())
+    {
+      (Some(first), Some(last)) => (*first, *last),
+      _ => return Ok(Vec::new()),
+    };
+
+    //computed in i128 as the distance between two millisecond timestamps can overflow
+    let bucket_count = (last as i128 - first as i128 + 1) as u128;
+    if bucket_count > MAX_HISTOGRAM_BUCKETS as u128
+    {
+      return Err(anyhow!("Histogram would need {} buckets, more than the maximum of {}", bucket_count, MAX_HISTOGRAM_BUCKETS))
+    }
+
+    let mut counts = vec![0; bucket_count as usize];
+    for index in indexes
+    {
+      counts[(index - first) as usize] += 1;
+    }
+
+    Ok(counts.into_iter().enumerate().map(|(offset, count)| (Utc.timestamp_millis((first + offset as i64) * bucket), count)).collect())
+  }
+
   fn match_time(node : &Node, node_id : &TreeNodeId, min_time : &DateTime<Utc>, max_time : &DateTime<Utc> ) -> Vec<TimeInfo>
   {      
     let mut times = Vec::new();
+    let name = node.name();
     for attribute in node.value().attributes().iter()
     {
-      Timeline::match_time_rec("".into(), &node_id, &attribute, &mut times, &min_time, &max_time);
+      Timeline::match_time_rec("", &node_id, &name, &attribute, &mut times, &min_time, &max_time);
     }
     times
   }
 
-  fn match_time_rec(dotted_attrib: String, node_id : &TreeNodeId, attribute : &Attribute, mut times : &mut Vec<TimeInfo>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>)
+  fn match_time_rec(dotted_attrib: &str, node_id : &TreeNodeId, name : &str, attribute : &Attribute, mut times : &mut Vec<TimeInfo>, min_time : &DateTime<Utc>, max_time : &DateTime<Utc>)
   {
     if attribute.type_id() == ValueTypeId::Attributes
     {
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
       for current_attribute in attribute.value().as_attributes().attributes().iter()
       {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        Timeline::match_time_rec(dotted_attrib, &node_id, &current_attribute, &mut times, &min_time, &max_time)
+        Timeline::match_time_rec(&dotted_attrib, &node_id, name, &current_attribute, &mut times, &min_time, &max_time)
       }
     }
     else if attribute.type_id() == ValueTypeId::ReflectStruct 
     {
       let attributes : Vec<Attribute> = attribute.value().as_reflect_struct().attributes();
+      let dotted_attrib = child_dotted_name(dotted_attrib, attribute);
       for current_attribute in attributes.iter() 
       {
-        let dotted_attrib = match dotted_attrib.len() 
-        {
-          0 => attribute.name().to_string(),
-          _ => dotted_attrib.to_string() + "." + attribute.name(),
-        };
-        Timeline::match_time_rec(dotted_attrib, &node_id, &current_attribute, &mut times, &min_time, &max_time)
+        Timeline::match_time_rec(&dotted_attrib, &node_id, name, &current_attribute, &mut times, &min_time, &max_time)
       }
     }
     else if attribute.type_id() == ValueTypeId::DateTime
@@ -111,10 +343,51 @@ impl Timeline
       {
         match dotted_attrib.len() 
         {
-          0 => times.push(TimeInfo{time : attribute_time, id : *node_id, attribute_name : attribute.name().to_string()}),
-          _ => times.push(TimeInfo{time : attribute_time, id : *node_id, attribute_name : dotted_attrib + "." + attribute.name()}),
+          0 => times.push(TimeInfo{time : attribute_time, id : *node_id, attribute_name : attribute.name().to_string(), name : name.to_string()}),
+          _ => times.push(TimeInfo{time : attribute_time, id : *node_id, attribute_name : dotted_attrib.to_string() + "." + attribute.name(), name : name.to_string()}),
         }
       }
     }
   }
 }
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn nodes_sorted_by_time()
+  {
+    let tree = Tree::new();
+    //shuffled times including the extremes a corrupted tree can produce
+    let mut expected : Vec<DateTime<Utc>> = (0..2000).map(|index| Utc.timestamp((index * 7919 % 2000) * 3600, 0)).collect();
+    expected.push(Utc.timestamp(0, 0));
+    expected.push(Utc.ymd(9999, 12, 31).and_hms(23, 59, 59));
+    for (index, time) in expected.iter().enumerate()
+    {
+      let node = Node::new(format!("node{}", index));
+      node.value().add_attribute("modified", *time, None);
+      tree.add_child(tree.root_id, node).unwrap();
+    }
+    let nodes = tree.children_rec(None).unwrap();
+
+    let times = Timeline::nodes(&tree, &nodes, &Utc.timestamp(0, 0), &Utc.ymd(9999, 12, 31).and_hms(23, 59, 59));
+    expected.sort();
+    assert_eq!(times.iter().map(|time| time.time).collect::<Vec<_>>(), expected);
+  }
+
+  #[test]
+  fn nodes_cancelled_error()
+  {
+    let tree = Tree::new();
+    let node = Node::new("node");
+    node.value().add_attribute("modified", Utc.timestamp(3600, 0), None);
+    tree.add_child(tree.root_id, node).unwrap();
+    let nodes = tree.children_rec(None).unwrap();
+
+    let cancel = AtomicBool::new(true);
+    let err = Timeline::nodes_cancellable(&tree, &nodes, &Utc.timestamp(0, 0), &Utc.timestamp(7200, 0), &cancel).unwrap_err();
+    assert!(err.downcast_ref::<QueryCancelled>().is_some());
+  }
+}